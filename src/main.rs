@@ -6,35 +6,104 @@
 // imports
 use tcod::colors::*;
 use tcod::console::*;
+use tcod::input::{self, Event, Key, KeyCode, Mouse};
 use tcod::map::{FovAlgorithm, Map as FovMap};
 use std::cmp;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::error::Error;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use rand::distributions::{IndependentSample, Weighted, WeightedChoice};
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 // size of window
 const SCREEN_WIDTH: i32 = 80;
 const SCREEN_HEIGHT: i32 = 50;
 
-// size of the map
-const MAP_WIDTH: i32 = 80;
-const MAP_HEIGHT: i32 = 43;
+// size of the map, can be bigger than the screen
+const MAP_WIDTH: i32 = 100;
+const MAP_HEIGHT: i32 = 60;
 
 // sizes for GUI
 const BAR_WIDTH: i32 = 20;
 const PANEL_HEIGHT: i32 = 7;
 const PANEL_Y: i32 = SCREEN_HEIGHT - PANEL_HEIGHT;
 
+// part of the map on screen at once, everything above the panel
+const CAMERA_WIDTH: i32 = SCREEN_WIDTH;
+const CAMERA_HEIGHT: i32 = SCREEN_HEIGHT - PANEL_HEIGHT;
+
 // message pos size
 const MSG_X: i32 = BAR_WIDTH + 2;
 const MSG_WIDTH: i32 = SCREEN_WIDTH - BAR_WIDTH - 2;
 const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
+// messages kept before the oldest get dropped, --history overrides it
+const MSG_HISTORY: usize = 1000;
+// where the message log viewer writes the log
+const MESSAGE_LOG_FILE: &str = "messages.txt";
 
 // size of rooms for dungeon generator
 const ROOM_MAX_SIZE: i32 = 10;
 const ROOM_MIN_SIZE: i32 = 6;
-const MAX_ROOMS: i32 = 17;
-
-// max monsters per rm
-const MAX_ROOM_MONSTERS: i32 = 3;
+const MAX_ROOMS: i32 = 30;
+
+// bsp generator: smallest leaf side and how many times to split
+const BSP_MIN_LEAF_SIZE: i32 = 10;
+const BSP_DEPTH: u32 = 5;
+
+// cave generator: starting wall density, smoothing passes, smallest
+// acceptable cave and how content gets spread over it
+const CAVE_WALL_CHANCE: f32 = 0.45;
+const CAVE_SMOOTHING_STEPS: u32 = 5;
+const CAVE_MIN_FLOOR: usize = 600;
+const CAVE_SPAWN_AREAS: usize = 24;
+const CAVE_SPAWN_RADIUS: i32 = 3;
+
+// max items per rm
+const MAX_ROOM_ITEMS: i32 = 2;
+
+// inventory capacity, one letter per slot
+const INVENTORY_SIZE: usize = 26;
+
+// width of inventory menu
+const INVENTORY_WIDTH: i32 = 50;
+
+// spell and potion stats
+const HEAL_AMOUNT: i32 = 10;
+// resting gets 1 hp back every few turns, and gives up after a while
+const REST_MAX_TURNS: i32 = 200;
+const LIGHTNING_DAMAGE: i32 = 20;
+const LIGHTNING_RANGE: i32 = 5;
+const CONFUSE_RANGE: i32 = 8;
+const CONFUSE_NUM_TURNS: i32 = 10;
+const FIREBALL_RANGE: i32 = 8;
+const FIREBALL_RADIUS: i32 = 3;
+const FIREBALL_DAMAGE: i32 = 12;
+const STUN_RANGE: i32 = 8;
+const STUN_NUM_TURNS: i32 = 3;
+const HASTE_NUM_TURNS: i32 = 20;
+const REGEN_NUM_TURNS: i32 = 20;
+const REGEN_AMOUNT: i32 = 1;
+
+// width of main menu and message boxes
+const MAIN_MENU_WIDTH: i32 = 24;
+const MSGBOX_WIDTH: i32 = 50;
+
+// title screen banner
+const TITLE_BANNER: &[&str] = &[
+    "#################################################",
+    "#                                               #",
+    "#   Y E T   A N O T H E R   R O G U E L I K E   #",
+    "#                                               #",
+    "#################################################",
+];
+
+// experience needed for next level is base + level * factor
+const LEVEL_UP_BASE: i32 = 200;
+const LEVEL_UP_FACTOR: i32 = 150;
+const LEVEL_SCREEN_WIDTH: i32 = 40;
 
 // colors of map elements
 const COLOR_DARK_WALL: Color = Color {r: 0, g: 50, b:50};
@@ -49,12 +118,40 @@ const FOV_ALGO: FovAlgorithm = FovAlgorithm::Shadow;
 const FOV_LIGHT_WALLS: bool = true;
 const TORCH_RADIUS: i32 = 10;
 
+// flee maps scale distances by -FLEE_FACTOR / 10, over 1 so fleeing
+// monsters prefer open space to corners
+const FLEE_FACTOR: i32 = 12;
+// monsters find their way to the player over at most this many steps,
+// further out they head straight at them
+const CHASE_RANGE: i32 = 3 * TORCH_RADIUS;
+
+// turn scheduling: everything with a fighter gains its speed in energy
+// each tick, acting costs energy
+const NORMAL_SPEED: i32 = 10;
+const MOVE_COST: i32 = 100;
+const ATTACK_COST: i32 = 100;
+// quaffing or reading is quicker than walking
+const ITEM_COST: i32 = 50;
+
 // frames per second 
 const LIMIT_FPS: i32 = 20; 
 
 // player first object
 const PLAYER: usize = 0;
 
+// monster templates, read at startup
+const MONSTERS_FILE: &str = "monsters.toml";
+
+// key bindings, read at startup. the shipped file doubles as the defaults
+const KEYS_FILE: &str = "keys.toml";
+const DEFAULT_KEYS: &str = include_str!("../keys.toml");
+const HELP_WIDTH: i32 = 50;
+
+// save file location and format version
+// bump version whenever saved structs change
+const SAVE_FILE: &str = "savegame";
+const SAVE_VERSION: u64 = 11;
+
 
 
 
@@ -63,12 +160,145 @@ const PLAYER: usize = 0;
 ////////////////////////////////////////////
 ///////////////
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 enum Ai {
     Basic,
+    // wanders randomly, then goes back to previous ai
+    Confused {
+        previous_ai: Box<Ai>,
+        num_turns: i32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Item {
+    Heal,
+    Lightning,
+    Confuse,
+    Fireball,
+    Stun,
+    Haste,
+    Regeneration,
+    Sword,
+    Shield,
+    Armor,
+}
+
+// timed conditions, counted in the affected object's own turns
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum EffectKind {
+    // loses strength hp each turn
+    Poison,
+    // skips its turns
+    Stun,
+    // gains energy twice as fast
+    Haste,
+    // gets strength hp back each turn
+    Regeneration,
+}
+
+impl EffectKind {
+    // short tag shown on the panel
+    pub fn tag(self) -> &'static str {
+        match self {
+            EffectKind::Poison => "PSN",
+            EffectKind::Stun => "STN",
+            EffectKind::Haste => "HST",
+            EffectKind::Regeneration => "REG",
+        }
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            EffectKind::Poison => LIME,
+            EffectKind::Stun => YELLOW,
+            EffectKind::Haste => LIGHT_BLUE,
+            EffectKind::Regeneration => LIGHT_RED,
+        }
+    }
+
+    // poison piles up when applied again, the others just refresh
+    pub fn stacks(self) -> bool {
+        self == EffectKind::Poison
+    }
+
+    pub fn start_message(self) -> &'static str {
+        match self {
+            EffectKind::Poison => "is poisoned!",
+            EffectKind::Stun => "is stunned!",
+            EffectKind::Haste => "speeds up!",
+            EffectKind::Regeneration => "starts to regenerate.",
+        }
+    }
+
+    pub fn end_message(self) -> &'static str {
+        match self {
+            EffectKind::Poison => "is no longer poisoned.",
+            EffectKind::Stun => "is no longer stunned.",
+            EffectKind::Haste => "slows down again.",
+            EffectKind::Regeneration => "stops regenerating.",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Effect {
+    kind: EffectKind,
+    turns: i32,
+    // hp per turn for poison and regeneration, unused otherwise
+    #[serde(default = "default_strength")]
+    strength: i32,
 }
 
+fn default_strength() -> i32 {
+    1
+}
+
+// what was picked in targeting mode
 #[derive(Clone, Copy, Debug, PartialEq)]
+enum Target {
+    Tile(i32, i32),
+    Object(usize),
+}
+
+// whether an item got used or should stay in inventory
+enum UseResult {
+    UsedUp,
+    UsedAndKept,
+    Cancelled,
+}
+
+// where a piece of equipment is worn
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Slot {
+    LeftHand,
+    RightHand,
+    Body,
+}
+
+impl std::fmt::Display for Slot {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Slot::LeftHand => write!(f, "left hand"),
+            Slot::RightHand => write!(f, "right hand"),
+            Slot::Body => write!(f, "body"),
+        }
+    }
+}
+
+// an object that can be equipped, yielding bonuses
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+struct Equipment {
+    slot: Slot,
+    equipped: bool,
+    power_bonus: i32,
+    defense_bonus: i32,
+    max_hp_bonus: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 enum DeathCallback {
     Player,
     Monster,
@@ -87,22 +317,408 @@ impl DeathCallback {
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 enum PlayerAction {
-    TookTurn,
+    // acted, costing this much energy
+    TookTurn(i32),
     DidntTakeTurn,
     Exit,
 }
 
 
+// things a key can be bound to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Command {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    MoveUpLeft,
+    MoveUpRight,
+    MoveDownLeft,
+    MoveDownRight,
+    Wait,
+    Rest,
+    Explore,
+    TravelToStairs,
+    PickUp,
+    Inventory,
+    DropItem,
+    Descend,
+    MessageLog,
+    Help,
+    Fullscreen,
+    Quit,
+}
+
+impl Command {
+    // every command, in the order the help screen lists them
+    const ALL: &'static [Command] = &[
+        Command::MoveUp,
+        Command::MoveDown,
+        Command::MoveLeft,
+        Command::MoveRight,
+        Command::MoveUpLeft,
+        Command::MoveUpRight,
+        Command::MoveDownLeft,
+        Command::MoveDownRight,
+        Command::Wait,
+        Command::Rest,
+        Command::Explore,
+        Command::TravelToStairs,
+        Command::PickUp,
+        Command::Inventory,
+        Command::DropItem,
+        Command::Descend,
+        Command::MessageLog,
+        Command::Help,
+        Command::Fullscreen,
+        Command::Quit,
+    ];
+
+    // name used in the keys file
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::MoveUp => "move_up",
+            Command::MoveDown => "move_down",
+            Command::MoveLeft => "move_left",
+            Command::MoveRight => "move_right",
+            Command::MoveUpLeft => "move_up_left",
+            Command::MoveUpRight => "move_up_right",
+            Command::MoveDownLeft => "move_down_left",
+            Command::MoveDownRight => "move_down_right",
+            Command::Wait => "wait",
+            Command::Rest => "rest",
+            Command::Explore => "explore",
+            Command::TravelToStairs => "travel_to_stairs",
+            Command::PickUp => "pick_up",
+            Command::Inventory => "inventory",
+            Command::DropItem => "drop_item",
+            Command::Descend => "descend",
+            Command::MessageLog => "message_log",
+            Command::Help => "help",
+            Command::Fullscreen => "fullscreen",
+            Command::Quit => "quit",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Command::MoveUp => "Move/attack up",
+            Command::MoveDown => "Move/attack down",
+            Command::MoveLeft => "Move/attack left",
+            Command::MoveRight => "Move/attack right",
+            Command::MoveUpLeft => "Move/attack up-left",
+            Command::MoveUpRight => "Move/attack up-right",
+            Command::MoveDownLeft => "Move/attack down-left",
+            Command::MoveDownRight => "Move/attack down-right",
+            Command::Wait => "Wait a turn",
+            Command::Rest => "Rest until healed",
+            Command::Explore => "Auto-explore",
+            Command::TravelToStairs => "Travel to stairs",
+            Command::PickUp => "Pick up",
+            Command::Inventory => "Use item",
+            Command::DropItem => "Drop item",
+            Command::Descend => "Go down stairs",
+            Command::MessageLog => "Message log",
+            Command::Help => "This help",
+            Command::Fullscreen => "Toggle fullscreen",
+            Command::Quit => "Save and quit",
+        }
+    }
+}
+
+// one key as written in the keys file
+#[derive(Clone, Debug, PartialEq)]
+struct KeySpec {
+    name: String,
+    code: KeyCode,
+    // typed character, for text keys only
+    text: String,
+    alt: bool,
+    ctrl: bool,
+}
+
+impl KeySpec {
+    // reads names like "k", "kp8", "pageup" or "alt+enter"
+    pub fn parse(name: &str) -> Option<KeySpec> {
+        use tcod::input::KeyCode::*;
+
+        let mut rest = name;
+        let (mut alt, mut ctrl) = (false, false);
+        loop {
+            if let Some(stripped) = rest.strip_prefix("alt+") {
+                alt = true;
+                rest = stripped;
+            } else if let Some(stripped) = rest.strip_prefix("ctrl+") {
+                ctrl = true;
+                rest = stripped;
+            } else {
+                break;
+            }
+        }
+
+        let code = match rest {
+            "up" => Up,
+            "down" => Down,
+            "left" => Left,
+            "right" => Right,
+            "enter" => Enter,
+            "esc" => Escape,
+            "space" => Spacebar,
+            "tab" => Tab,
+            "backspace" => Backspace,
+            "pageup" => PageUp,
+            "pagedown" => PageDown,
+            "home" => Home,
+            "end" => End,
+            "insert" => Insert,
+            "delete" => Delete,
+            "kp0" => NumPad0,
+            "kp1" => NumPad1,
+            "kp2" => NumPad2,
+            "kp3" => NumPad3,
+            "kp4" => NumPad4,
+            "kp5" => NumPad5,
+            "kp6" => NumPad6,
+            "kp7" => NumPad7,
+            "kp8" => NumPad8,
+            "kp9" => NumPad9,
+            "kpenter" => NumPadEnter,
+            "f1" => F1,
+            "f2" => F2,
+            "f3" => F3,
+            "f4" => F4,
+            "f5" => F5,
+            "f6" => F6,
+            "f7" => F7,
+            "f8" => F8,
+            "f9" => F9,
+            "f10" => F10,
+            "f11" => F11,
+            "f12" => F12,
+            _ if rest.chars().count() == 1 => Text,
+            _ => return None,
+        };
+        let text = if code == Text { rest.to_string() } else { String::new() };
+        Some(KeySpec {
+            name: name.to_string(),
+            code,
+            text,
+            alt,
+            ctrl,
+        })
+    }
+
+    pub fn matches(&self, key: Key) -> bool {
+        key.code == self.code
+            && (self.code != KeyCode::Text || key.text() == self.text)
+            && key.alt == self.alt
+            && key.ctrl == self.ctrl
+    }
+}
+
+// which keys run which command
+#[derive(Clone, Debug, Default)]
+struct Keymap {
+    bindings: HashMap<Command, Vec<KeySpec>>,
+}
+
+impl Keymap {
+    pub fn command_for(&self, key: Key) -> Option<Command> {
+        Command::ALL.iter().cloned().find(|command| {
+            self.keys_for(*command).iter().any(|spec| spec.matches(key))
+        })
+    }
+
+    pub fn keys_for(&self, command: Command) -> &[KeySpec] {
+        self.bindings.get(&command).map_or(&[], |keys| keys.as_slice())
+    }
+}
+
 // holds libtcod related values
-struct Tcod {
-  root: Root,
+struct Tcod<'a> {
+  backend: &'a mut dyn Backend,
+  screen: Offscreen,
   con: Offscreen,
   panel: Offscreen,
   fov: FovMap,
+  key: Key,
+  mouse: Mouse,
+  keymap: Keymap,
+  // map coords of the top left corner of the screen
+  camera: (i32, i32),
+}
+
+// where finished frames are shown and where keys come from
+trait Backend {
+    // show the composed screen to the player
+    fn present(&mut self, screen: &Offscreen);
+    // next key press or mouse event, doesn't block
+    fn check_for_event(&mut self) -> Option<Event>;
+    // whether a key press is waiting, without taking it. long actions
+    // like exploring poll this to stop early
+    fn key_pending(&mut self) -> bool;
+    // block until the next key press
+    fn wait_for_keypress(&mut self) -> Key;
+    fn window_closed(&self) -> bool;
+    fn is_fullscreen(&self) -> bool;
+    fn set_fullscreen(&mut self, fullscreen: bool);
+}
+
+// real window thru libtcod
+struct TcodBackend {
+    root: Root,
+    // key press seen by key_pending, handed out by the next check_for_event
+    pending: Option<Event>,
+}
+
+impl Backend for TcodBackend {
+    fn present(&mut self, screen: &Offscreen) {
+        blit(
+            screen,
+            (0, 0),
+            (SCREEN_WIDTH, SCREEN_HEIGHT),
+            &mut self.root,
+            (0, 0),
+            1.0,
+            1.0,
+        );
+        self.root.flush();
+    }
+
+    fn check_for_event(&mut self) -> Option<Event> {
+        self.pending
+            .take()
+            .or_else(|| input::check_for_event(input::MOUSE | input::KEY_PRESS).map(|(_, event)| event))
+    }
+
+    fn key_pending(&mut self) -> bool {
+        if self.pending.is_none() {
+            self.pending = input::check_for_event(input::KEY_PRESS).map(|(_, event)| event);
+        }
+        self.pending.is_some()
+    }
+
+    fn wait_for_keypress(&mut self) -> Key {
+        match self.pending.take() {
+            Some(Event::Key(key)) => key,
+            _ => self.root.wait_for_keypress(true),
+        }
+    }
+
+    fn window_closed(&self) -> bool {
+        self.root.window_closed()
+    }
+
+    fn is_fullscreen(&self) -> bool {
+        self.root.is_fullscreen()
+    }
+
+    fn set_fullscreen(&mut self, fullscreen: bool) {
+        self.root.set_fullscreen(fullscreen);
+    }
+}
+
+// one character on the headless screen
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Cell {
+    char: char,
+    foreground: Color,
+    background: Color,
+}
+
+// no window, keeps last frame in memory and plays back scripted events
+// closes itself once the script runs out
+struct HeadlessBackend {
+    cells: Vec<Cell>,
+    events: VecDeque<Event>,
+    closed: bool,
+    fullscreen: bool,
+}
+
+impl HeadlessBackend {
+    pub fn new(events: Vec<Event>) -> Self {
+        let blank = Cell {
+            char: ' ',
+            foreground: WHITE,
+            background: BLACK,
+        };
+        HeadlessBackend {
+            cells: vec![blank; (SCREEN_WIDTH * SCREEN_HEIGHT) as usize],
+            events: events.into(),
+            closed: false,
+            fullscreen: false,
+        }
+    }
+
+    // what was last presented at given screen position
+    pub fn cell(&self, x: i32, y: i32) -> Cell {
+        self.cells[(y * SCREEN_WIDTH + x) as usize]
+    }
+
+    // characters of last frame, one line per row
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for y in 0..SCREEN_HEIGHT {
+            let line: String = (0..SCREEN_WIDTH).map(|x| self.cell(x, y).char).collect();
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+        text
+    }
+}
+
+impl Backend for HeadlessBackend {
+    fn present(&mut self, screen: &Offscreen) {
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                self.cells[(y * SCREEN_WIDTH + x) as usize] = Cell {
+                    char: screen.get_char(x, y),
+                    foreground: screen.get_char_foreground(x, y),
+                    background: screen.get_char_background(x, y),
+                };
+            }
+        }
+    }
+
+    fn check_for_event(&mut self) -> Option<Event> {
+        let event = self.events.pop_front();
+        if event.is_none() {
+            self.closed = true;
+        }
+        event
+    }
+
+    // scripted events are commands for the main loop, nothing gets
+    // typed while an action runs by itself
+    fn key_pending(&mut self) -> bool {
+        false
+    }
+
+    fn wait_for_keypress(&mut self) -> Key {
+        // mouse events don't answer a key prompt
+        while let Some(event) = self.check_for_event() {
+            if let Event::Key(key) = event {
+                return key;
+            }
+        }
+        Key::default()
+    }
+
+    fn window_closed(&self) -> bool {
+        self.closed
+    }
+
+    fn is_fullscreen(&self) -> bool {
+        self.fullscreen
+    }
+
+    fn set_fullscreen(&mut self, fullscreen: bool) {
+        self.fullscreen = fullscreen;
+    }
 }
 
 // a tile of the map and its properties
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 struct Tile {
     blocked: bool,
     explored: bool,
@@ -127,21 +743,80 @@ impl Tile {
     }
 }
 
+// one log entry, count goes up when the same message comes in again
+#[derive(Serialize, Deserialize)]
+struct Message {
+    text: String,
+    color: Color,
+    count: u32,
+}
+
+impl Message {
+    // text as shown, w/ the repeat counter
+    pub fn line(&self) -> String {
+        if self.count > 1 {
+            format!("{} x{}", self.text, self.count)
+        } else {
+            self.text.clone()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 struct Messages {
-    messages: Vec<(String, Color)>,
+    messages: Vec<Message>,
+    // oldest messages are dropped past this many
+    capacity: usize,
 }
 impl Messages {
-    pub fn new() -> Self {
-        Self { messages: vec![]}
+    pub fn new(capacity: usize) -> Self {
+        Self { messages: vec![], capacity }
     }
-    // add new msg as a tuple w/ txt and color
+    // add new msg w/ txt and color, or bump the last one if it's the same
     pub fn add<T: Into<String>>(&mut self, message: T, color: Color) {
-        self.messages.push((message.into(), color));
+        let text = message.into();
+        if let Some(last) = self.messages.last_mut() {
+            if last.text == text && last.color == color {
+                last.count += 1;
+                return;
+            }
+        }
+        self.messages.push(Message { text, color, count: 1 });
+        if self.messages.len() > self.capacity {
+            let excess = self.messages.len() - self.capacity;
+            self.messages.drain(..excess);
+        }
+    }
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        if self.messages.len() > capacity {
+            let excess = self.messages.len() - capacity;
+            self.messages.drain(..excess);
+        }
     }
     // create doubleendediterator over messages
-    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &(String, Color)> {
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Message> {
         self.messages.iter()
     }
+    // writes whole log to a text file, one message per line
+    pub fn dump(&self, path: &str) -> std::io::Result<()> {
+        let mut file = File::create(path)?;
+        for message in &self.messages {
+            writeln!(file, "{}", message.line())?;
+        }
+        Ok(())
+    }
+}
+
+// how levels get laid out
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum MapGenerator {
+    // random rooms joined in the order they were made
+    Rooms,
+    // binary space partition, one room per leaf
+    Bsp,
+    // cellular automata caves, no rooms
+    Caves,
 }
 
 // rectangle on map ie a room
@@ -169,6 +844,16 @@ impl Rect {
         let center_y = (self.y1 + self.y2) / 2;
         (center_x, center_y)
     }
+    // tiles inside the walls, where things can be placed
+    pub fn tiles(&self) -> Vec<(i32, i32)> {
+        let mut tiles = vec![];
+        for x in (self.x1 + 1)..self.x2 {
+            for y in (self.y1 + 1)..self.y2 {
+                tiles.push((x, y));
+            }
+        }
+        tiles
+    }
     // returns true if rect intersects with another one
     pub fn intersects_with(&self, other: &Rect) -> bool {
         (self.x1 <= other.x2)
@@ -178,21 +863,131 @@ impl Rect {
     }
 }
 
-// combat related properties and methods (monster, player, npc)
-#[derive(Clone, Copy, Debug, PartialEq)]
-struct Fighter {
-    max_hp: i32,
+// seeded random number generator (splitmix64)
+// owned by the game so the same seed always gives the same run
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct GameRng {
+    state: u64,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng { state: seed }
+    }
+}
+
+impl Rng for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+// ai a monster template can start with
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum AiKind {
+    Basic,
+}
+
+// monster stats as written in the monsters file
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MonsterTemplate {
+    name: String,
+    glyph: char,
+    color: [u8; 3],
     hp: i32,
     defense: i32,
     power: i32,
+    xp: i32,
+    ai: AiKind,
+    spawn_weight: Vec<Transition>,
+    #[serde(default = "normal_speed")]
+    speed: i32,
+    #[serde(default)]
+    on_hit: Option<Effect>,
+}
+
+// monsters without a speed in the file move like the player
+fn normal_speed() -> i32 {
+    NORMAL_SPEED
+}
+
+impl MonsterTemplate {
+    // creates a live monster from this template
+    pub fn spawn(&self, x: i32, y: i32) -> Object {
+        let [r, g, b] = self.color;
+        let mut monster = Object::new(x, y, self.glyph, &self.name, Color { r, g, b }, true);
+        monster.alive = true;
+        monster.fighter = Some(Fighter {
+            base_max_hp: self.hp,
+            hp: self.hp,
+            base_defense: self.defense,
+            base_power: self.power,
+            xp: self.xp,
+            level: 1,
+            on_death: DeathCallback::Monster,
+            on_hit: self.on_hit,
+        });
+        monster.ai = Some(match self.ai {
+            AiKind::Basic => Ai::Basic,
+        });
+        monster.speed = self.speed;
+        monster
+    }
+}
+
+// layout of the monsters file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MonsterFile {
+    monster: Vec<MonsterTemplate>,
+}
+
+// value that changes with dungeon depth, from given level on
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(from = "(u32, u32)")]
+struct Transition {
+    level: u32,
+    value: u32,
+}
+
+// written as [level, value] in data files
+impl From<(u32, u32)> for Transition {
+    fn from((level, value): (u32, u32)) -> Self {
+        Transition { level, value }
+    }
+}
+
+// combat related properties and methods (monster, player, npc)
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+// base stats, equipment bonuses are added on top
+struct Fighter {
+    base_max_hp: i32,
+    hp: i32,
+    base_defense: i32,
+    base_power: i32,
+    // xp held by player, or given to killer for monsters
+    xp: i32,
+    level: i32,
     on_death: DeathCallback,
+    // put on whoever this fighter hits
+    on_hit: Option<Effect>,
 }
 
 
 
 
 // generic object, represented by a character on screen
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 struct Object {
     x: i32,
     y: i32,
@@ -201,8 +996,15 @@ struct Object {
     name: String,
     blocks: bool,
     alive: bool,
+    always_visible: bool,
     fighter: Option<Fighter>,
     ai: Option<Ai>,
+    item: Option<Item>,
+    equipment: Option<Equipment>,
+    // energy gained per tick, and energy saved up. acts while energy >= 0
+    speed: i32,
+    energy: i32,
+    effects: Vec<Effect>,
 }
 
 impl Object {
@@ -215,16 +1017,24 @@ impl Object {
             name: name.into(),
             blocks: blocks,
             alive: false,
+            always_visible: false,
             fighter: None,
             ai: None,
+            item: None,
+            equipment: None,
+            speed: NORMAL_SPEED,
+            energy: 0,
+            effects: vec![],
         }
     }
 
     
     // set color and draw character representing object
-    pub fn draw(&self, con: &mut dyn Console) {
-        con.set_default_foreground(self.color);
-        con.put_char(self.x, self.y, self.char, BackgroundFlag::None);
+    pub fn draw(&self, con: &mut dyn Console, camera: (i32, i32)) {
+        if let Some((x, y)) = to_camera(self.x, self.y, camera) {
+            con.set_default_foreground(self.color);
+            con.put_char(x, y, self.char, BackgroundFlag::None);
+        }
     }
 
     // returns distance to another object
@@ -234,7 +1044,13 @@ impl Object {
         ((dx.pow(2) + dy.pow(2)) as f32).sqrt()
     }
 
-    pub fn take_damage(&mut self, damage: i32, game: &mut Game) {
+    // returns distance to some coordinates
+    pub fn distance(&self, x: i32, y: i32) -> f32 {
+        (((x - self.x).pow(2) + (y - self.y).pow(2)) as f32).sqrt()
+    }
+
+    // returns xp of object if damage killed it
+    pub fn take_damage(&mut self, damage: i32, game: &mut Game) -> Option<i32> {
         // apply damage if possible
         if let Some(fighter) = self.fighter.as_mut() {
             if damage > 0 {
@@ -246,13 +1062,15 @@ impl Object {
             if fighter.hp <= 0 {
                 self.alive = false;
                 fighter.on_death.callback(self, game);
+                return Some(fighter.xp);
             }
         }
+        None
     }
 
     pub fn attack(&mut self, target: &mut Object, game: &mut Game) {
         // a simple formula for attack damage
-        let damage = self.fighter.map_or(0, |f| f.power) - target.fighter.map_or(0, |f| f.defense);
+        let damage = self.power(game) - target.defense(game);
         if damage > 0 {
             // make target take damage
             game.messages.add(
@@ -261,7 +1079,12 @@ impl Object {
                 ),
                 WHITE,
             );
-            target.take_damage(damage, game);
+            if let Some(xp) = target.take_damage(damage, game) {
+                // killer collects the xp
+                self.fighter.as_mut().unwrap().xp += xp;
+            } else if let Some(effect) = self.fighter.and_then(|f| f.on_hit) {
+                target.add_effect(effect, &mut game.messages);
+            }
         } else {
             game.messages.add(
                 format!(
@@ -273,11 +1096,154 @@ impl Object {
         }
     }
 
+    // starts an effect, or stacks/refreshes it if already running
+    pub fn add_effect(&mut self, effect: Effect, messages: &mut Messages) {
+        match self.effects.iter_mut().find(|e| e.kind == effect.kind) {
+            Some(current) if effect.kind.stacks() => {
+                current.turns += effect.turns;
+                current.strength = cmp::max(current.strength, effect.strength);
+            }
+            Some(current) => {
+                current.turns = cmp::max(current.turns, effect.turns);
+                current.strength = cmp::max(current.strength, effect.strength);
+            }
+            None => {
+                messages.add(
+                    format!("{} {}", self.name, effect.kind.start_message()),
+                    effect.kind.color(),
+                );
+                self.effects.push(effect);
+            }
+        }
+    }
+
+    pub fn has_effect(&self, kind: EffectKind) -> bool {
+        self.effects.iter().any(|e| e.kind == kind)
+    }
+
+    // energy per tick incl. haste
+    pub fn current_speed(&self) -> i32 {
+        if self.has_effect(EffectKind::Haste) {
+            self.speed * 2
+        } else {
+            self.speed
+        }
+    }
+
+    // heal by given amount, without going over max
+    pub fn heal(&mut self, amount: i32, game: &Game) {
+        let max_hp = self.max_hp(game);
+        if let Some(ref mut fighter) = self.fighter {
+            fighter.hp = cmp::min(fighter.hp + amount, max_hp);
+        }
+    }
+
+    // attack incl. equipment bonuses
+    pub fn power(&self, game: &Game) -> i32 {
+        let base_power = self.fighter.map_or(0, |f| f.base_power);
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|e| e.power_bonus)
+            .sum();
+        base_power + bonus
+    }
+
+    // defense incl. equipment bonuses
+    pub fn defense(&self, game: &Game) -> i32 {
+        let base_defense = self.fighter.map_or(0, |f| f.base_defense);
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|e| e.defense_bonus)
+            .sum();
+        base_defense + bonus
+    }
+
+    // max hp incl. equipment bonuses
+    pub fn max_hp(&self, game: &Game) -> i32 {
+        let base_max_hp = self.fighter.map_or(0, |f| f.base_max_hp);
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|e| e.max_hp_bonus)
+            .sum();
+        base_max_hp + bonus
+    }
+
+    // equipment worn by this object, only the player carries any
+    pub fn get_all_equipped(&self, game: &Game) -> Vec<Equipment> {
+        if self.name == "player" {
+            game.inventory
+                .iter()
+                .filter_map(|item| item.equipment)
+                .filter(|e| e.equipped)
+                .collect()
+        } else {
+            vec![]
+        }
+    }
+
+    // equip object and show message
+    pub fn equip(&mut self, messages: &mut Messages) {
+        if self.item.is_none() {
+            messages.add(
+                format!("Can't equip {} because it's not an Item.", self.name),
+                RED,
+            );
+            return;
+        }
+        if let Some(ref mut equipment) = self.equipment {
+            if !equipment.equipped {
+                equipment.equipped = true;
+                messages.add(
+                    format!("Equipped {} on {}.", self.name, equipment.slot),
+                    LIGHT_GREEN,
+                );
+            }
+        } else {
+            messages.add(
+                format!("Can't equip {} because it's not an Equipment.", self.name),
+                RED,
+            );
+        }
+    }
+
+    // dequip object and show message
+    pub fn dequip(&mut self, messages: &mut Messages) {
+        if self.item.is_none() {
+            messages.add(
+                format!("Can't dequip {} because it's not an Item.", self.name),
+                RED,
+            );
+            return;
+        }
+        if let Some(ref mut equipment) = self.equipment {
+            if equipment.equipped {
+                equipment.equipped = false;
+                messages.add(
+                    format!("Dequipped {} from {}.", self.name, equipment.slot),
+                    LIGHT_YELLOW,
+                );
+            }
+        } else {
+            messages.add(
+                format!("Can't dequip {} because it's not an Equipment.", self.name),
+                RED,
+            );
+        }
+    }
+
     // getter position
     pub fn pos(&self) -> (i32, i32) {
         (self.x, self.y)
     }
 
+    // the way down, made by generate_map
+    pub fn is_stairs(&self) -> bool {
+        self.name == "stairs"
+    }
+
     // setter position
     pub fn set_pos(&mut self, x: i32, y:i32){
         self.x = x;
@@ -288,28 +1254,204 @@ impl Object {
 // defines the map
 type Map = Vec<Vec<Tile>>;
 
-struct Game {
-    map: Map,
-    messages: Messages,
+// distance from every tile to the closest goal, walking around walls. a
+// goal's weight is added to its distance, so lower weights pull harder.
+// rolling downhill from any tile leads to a goal. blocking objects move
+// around too much to be baked in, best_step steps around them instead
+#[derive(Clone, Debug, Default)]
+struct DijkstraMap {
+    distances: Vec<Vec<i32>>,
+    // what the distances were worked out from, to skip needless rebuilds
+    goals: Vec<((i32, i32), i32)>,
+    passable: Vec<Vec<bool>>,
+    // distances stop growing here, tiles further out count as unreachable
+    limit: i32,
+    // goals of the map a flee map was made from
+    fled_from: Vec<((i32, i32), i32)>,
 }
 
+impl DijkstraMap {
+    // tiles that can't be reached
+    const UNREACHABLE: i32 = i32::MAX;
 
+    // works out distances over the tiles passable accepts, unless those
+    // and the goals are the same as last time. returns whether anything
+    // was recomputed
+    pub fn update<P: Fn(&Tile) -> bool>(&mut self, map: &Map, goals: &[((i32, i32), i32)], passable: P) -> bool {
+        self.update_within(map, goals, Self::UNREACHABLE, passable)
+    }
 
-/// FUNCTIONS ///
-///////////////////////////////////////////
-///////////////////////////////////////////
-/////////////////
+    // same as update but only searches out to limit, so a far off goal
+    // doesn't mean flooding the whole map
+    pub fn update_within<P: Fn(&Tile) -> bool>(
+        &mut self,
+        map: &Map,
+        goals: &[((i32, i32), i32)],
+        limit: i32,
+        passable: P,
+    ) -> bool {
+        let same_tiles = self.passable.len() == map.len()
+            && self.passable.iter().zip(map).all(|(seen, column)| {
+                seen.len() == column.len() && seen.iter().zip(column).all(|(&seen, tile)| seen == passable(tile))
+            });
+        if same_tiles && self.goals == goals && self.limit == limit {
+            return false;
+        }
+        if !same_tiles {
+            self.passable = map
+                .iter()
+                .map(|column| column.iter().map(&passable).collect())
+                .collect();
+        }
+        self.goals.clear();
+        self.goals.extend_from_slice(goals);
+        self.limit = limit;
+        self.rebuild();
+        true
+    }
 
-// goes thru tiles in rect and makes them passable
-fn create_room(room: Rect, map: &mut Map) {
-    for x in (room.x1 + 1)..room.x2 {
-        for y in (room.y1 + 1)..room.y2 {
-            map[x as usize][y as usize] = Tile::empty();
+    // makes this a map leading away from other's goals, over the same
+    // tiles. scaled past plain negation so a cornered monster would
+    // rather run past. only recomputed when other changed
+    #[allow(dead_code)]
+    pub fn update_flee(&mut self, other: &DijkstraMap) -> bool {
+        if !self.distances.is_empty() && self.fled_from == other.goals && self.passable == other.passable {
+            return false;
+        }
+        self.goals.clear();
+        for (x, column) in other.distances.iter().enumerate() {
+            for (y, &distance) in column.iter().enumerate() {
+                if distance != Self::UNREACHABLE {
+                    self.goals.push(((x as i32, y as i32), -distance * FLEE_FACTOR / 10));
+                }
+            }
         }
+        self.fled_from.clear();
+        self.fled_from.extend_from_slice(&other.goals);
+        self.passable.clone_from(&other.passable);
+        self.limit = Self::UNREACHABLE;
+        self.rebuild();
+        true
     }
-}
 
-// horizontal tunnel. min and max used in case x1 > x2
+    // dijkstra out from the goals over passable tiles
+    fn rebuild(&mut self) {
+        let (width, height) = (self.passable.len(), self.passable.first().map_or(0, |column| column.len()));
+        if self.distances.len() == width && self.distances.first().map_or(0, |column| column.len()) == height {
+            for column in &mut self.distances {
+                for distance in column.iter_mut() {
+                    *distance = Self::UNREACHABLE;
+                }
+            }
+        } else {
+            self.distances = vec![vec![Self::UNREACHABLE; height]; width];
+        }
+
+        let distances = &mut self.distances;
+        let mut frontier = BinaryHeap::new();
+        // goals are seeded even when not passable, usually something stands there
+        for &((x, y), weight) in &self.goals {
+            if x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height && weight < distances[x as usize][y as usize] {
+                distances[x as usize][y as usize] = weight;
+                frontier.push(cmp::Reverse((weight, (x, y))));
+            }
+        }
+
+        while let Some(cmp::Reverse((distance, (x, y)))) = frontier.pop() {
+            // skip stale entries, a shorter way was already found, and
+            // don't go past the limit
+            if distance > distances[x as usize][y as usize] || distance >= self.limit {
+                continue;
+            }
+            // diagonal steps cost the same as straight ones
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if nx < 0 || ny < 0 || nx as usize >= width || ny as usize >= height {
+                        continue;
+                    }
+                    if !self.passable[nx as usize][ny as usize] {
+                        continue;
+                    }
+                    let next = distance + 1;
+                    if next < distances[nx as usize][ny as usize] {
+                        distances[nx as usize][ny as usize] = next;
+                        frontier.push(cmp::Reverse((next, (nx, ny))));
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn distance(&self, x: i32, y: i32) -> Option<i32> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+        self.distances
+            .get(x as usize)
+            .and_then(|column| column.get(y as usize))
+            .cloned()
+            .filter(|&distance| distance != Self::UNREACHABLE)
+    }
+
+    // direction of the lowest free neighbour that's lower than from
+    // itself, None if none of them leads further down
+    pub fn best_step(&self, from: (i32, i32), map: &Map, objects: &[Object]) -> Option<(i32, i32)> {
+        let mut best = self.distance(from.0, from.1).unwrap_or(Self::UNREACHABLE);
+        let mut step = None;
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                let (x, y) = (from.0 + dx, from.1 + dy);
+                if let Some(distance) = self.distance(x, y) {
+                    if distance < best && !is_blocked(x, y, map, objects) {
+                        best = distance;
+                        step = Some((dx, dy));
+                    }
+                }
+            }
+        }
+        step
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Game {
+    map: Map,
+    messages: Messages,
+    inventory: Vec<Object>,
+    dungeon_level: u32,
+    generator: MapGenerator,
+    seed: u64,
+    rng: GameRng,
+    // read from monsters file, not saved
+    #[serde(skip)]
+    monsters: Vec<MonsterTemplate>,
+    // shared by every monster, toward the player and away from them.
+    // nothing flees yet, the flee map is there for ai that wants it
+    #[serde(skip)]
+    chase_map: DijkstraMap,
+    #[serde(skip)]
+    #[allow(dead_code)]
+    flee_map: DijkstraMap,
+}
+
+
+
+/// FUNCTIONS ///
+///////////////////////////////////////////
+///////////////////////////////////////////
+/////////////////
+
+// goes thru tiles in rect and makes them passable
+fn create_room(room: Rect, map: &mut Map) {
+    for x in (room.x1 + 1)..room.x2 {
+        for y in (room.y1 + 1)..room.y2 {
+            map[x as usize][y as usize] = Tile::empty();
+        }
+    }
+}
+
+// horizontal tunnel. min and max used in case x1 > x2
 fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
     for x in cmp::min(x1, x2)..(cmp::max(x1, x2) +1) {
         map[x as usize][y as usize] = Tile::empty();
@@ -345,7 +1487,8 @@ fn move_by(id: usize, dx: i32, dy: i32, map: &Map, objects: &mut [Object]) {
 }
 
 // moves player or attacks monster
-fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut [Object]){
+// returns how much energy it took
+fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut [Object]) -> i32 {
     // coords player move/attack to
     let x = objects[PLAYER].x + dx;
     let y = objects[PLAYER].y + dy;
@@ -358,13 +1501,41 @@ fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut [Objec
         Some(target_id) => {
             let (player, target) = mut_two(PLAYER, target_id, objects);
             player.attack(target, game);
+            ATTACK_COST
         }
         None => {
             move_by(PLAYER, dx, dy, &game.map, objects);
+            MOVE_COST
         }
     }
 }
 
+// add to player inventory and remove from map
+fn pick_item_up(object_id: usize, game: &mut Game, objects: &mut Vec<Object>) {
+    if game.inventory.len() >= INVENTORY_SIZE {
+        game.messages.add(
+            format!("Your pockets are full, cannot pick up {}.", objects[object_id].name),
+            RED,
+        );
+    } else {
+        let item = objects.swap_remove(object_id);
+        game.messages.add(format!("You picked up a {}!", item.name), GREEN);
+        game.inventory.push(item);
+    }
+}
+
+// take out of inventory and put it down where the player stands
+fn drop_item(inventory_id: usize, game: &mut Game, objects: &mut Vec<Object>) {
+    let mut item = game.inventory.remove(inventory_id);
+    if item.equipment.is_some() {
+        item.dequip(&mut game.messages);
+        clamp_player_hp(game, objects);
+    }
+    item.set_pos(objects[PLAYER].x, objects[PLAYER].y);
+    game.messages.add(format!("You dropped a {}.", item.name), YELLOW);
+    objects.push(item);
+}
+
 fn player_death(player: &mut Object, game: &mut Game) {
     // game ended!
     game.messages.add("You died! Capitalism reigns supreme.", RED);
@@ -375,12 +1546,20 @@ fn player_death(player: &mut Object, game: &mut Game) {
 
 fn monster_death(monster: &mut Object, game: &mut Game) {
     // transform to corpse
-    game.messages.add(format!("{} is dead, yet surely, will be replaced.", monster.name), ORANGE);
+    game.messages.add(
+        format!(
+            "{} is dead, yet surely, will be replaced. You gain {} experience points.",
+            monster.name,
+            monster.fighter.map_or(0, |f| f.xp)
+        ),
+        ORANGE,
+    );
     monster.char = '%';
     monster.color = DARK_RED;
     monster.blocks = false;
     monster.fighter = None;
     monster.ai = None;
+    monster.effects.clear();
     monster.name = format!("remains of {}", monster.name)
 }
 
@@ -397,6 +1576,14 @@ fn move_towards(id: usize, target_x: i32, target_y: i32, map: &Map, objects: &mu
     move_by(id, dx, dy, map, objects);
 }
 
+// step downhill on a dijkstra map, straight at target if it has no way
+fn move_downhill(id: usize, dijkstra: &DijkstraMap, target: (i32, i32), map: &Map, objects: &mut [Object]) {
+    match dijkstra.best_step(objects[id].pos(), map, objects) {
+        Some((dx, dy)) => move_by(id, dx, dy, map, objects),
+        None => move_towards(id, target.0, target.1, map, objects),
+    }
+}
+
 /// mutably borrow 2 separate elements from given slice
 /// will panic when indexes are equal or oob
 fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (&mut T, &mut T) {
@@ -411,67 +1598,650 @@ fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (&mut
 }
 
 
-fn ai_take_turn(monster_id: usize, tcod: &Tcod, game: &mut Game, objects: &mut [Object]) {
+// returns how much energy the turn took
+fn ai_take_turn(monster_id: usize, tcod: &Tcod, game: &mut Game, objects: &mut [Object]) -> i32 {
+    use Ai::*;
+    // take ai out while it runs, put back what it turns into
+    if let Some(ai) = objects[monster_id].ai.take() {
+        let (new_ai, cost) = match ai {
+            Basic => ai_basic(monster_id, tcod, game, objects),
+            Confused {
+                previous_ai,
+                num_turns,
+            } => ai_confused(monster_id, game, objects, previous_ai, num_turns),
+        };
+        objects[monster_id].ai = Some(new_ai);
+        cost
+    } else {
+        MOVE_COST
+    }
+}
+
+fn ai_basic(monster_id: usize, tcod: &Tcod, game: &mut Game, objects: &mut [Object]) -> (Ai, i32) {
     // a basic monster takes its turn. if u can see it it can see u
     let (monster_x, monster_y) = objects[monster_id].pos();
     if tcod.fov.is_in_fov(monster_x, monster_y) {
+        let player_pos = objects[PLAYER].pos();
+        game.chase_map
+            .update_within(&game.map, &[(player_pos, 0)], CHASE_RANGE, |tile| !tile.blocked);
+
         if objects[monster_id].distance_to(&objects[PLAYER]) >= 2.0 {
             // move towards player if far away
-            let (player_x, player_y) = objects[PLAYER].pos();
-            move_towards(monster_id, player_x, player_y, &game.map, objects);
+            move_downhill(monster_id, &game.chase_map, player_pos, &game.map, objects);
         } else if objects[PLAYER].fighter.map_or(false, |f| f.hp >0) {
             // close enough - attack if player is still alive
             let (monster, player) = mut_two(monster_id, PLAYER, objects);
             monster.attack(player, game);
+            return (Ai::Basic, ATTACK_COST);
+        }
+    }
+    (Ai::Basic, MOVE_COST)
+}
+
+fn ai_confused(
+    monster_id: usize,
+    game: &mut Game,
+    objects: &mut [Object],
+    previous_ai: Box<Ai>,
+    num_turns: i32,
+) -> (Ai, i32) {
+    if num_turns >= 0 {
+        // still confused, stumble in a random direction
+        let dx = game.rng.gen_range(-1, 2);
+        let dy = game.rng.gen_range(-1, 2);
+        move_by(monster_id, dx, dy, &game.map, objects);
+        let ai = Ai::Confused {
+            previous_ai,
+            num_turns: num_turns - 1,
+        };
+        (ai, MOVE_COST)
+    } else {
+        // back to normal
+        game.messages.add(
+            format!("The {} is no longer confused!", objects[monster_id].name),
+            RED,
+        );
+        (*previous_ai, MOVE_COST)
+    }
+}
+
+// closest monster in fov within range, if any
+fn closest_monster(tcod: &Tcod, objects: &[Object], max_range: i32) -> Option<usize> {
+    let mut closest_enemy = None;
+    // start w/ slightly more than max range
+    let mut closest_dist = (max_range + 1) as f32;
+
+    for (id, object) in objects.iter().enumerate() {
+        if (id != PLAYER)
+            && object.fighter.is_some()
+            && object.ai.is_some()
+            && tcod.fov.is_in_fov(object.x, object.y)
+        {
+            let dist = objects[PLAYER].distance_to(object);
+            if dist < closest_dist {
+                closest_enemy = Some(id);
+                closest_dist = dist;
+            }
+        }
+    }
+    closest_enemy
+}
+
+// lets player aim w/ a cursor starting at themself, None if cancelled
+// only visible tiles within max range can be picked, w/ monsters_only
+// the tile must also hold a monster
+fn target_mode(
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &[Object],
+    max_range: Option<i32>,
+    monsters_only: bool,
+) -> Option<Target> {
+    use tcod::input::KeyCode::*;
+
+    let player = &objects[PLAYER];
+    let in_range = |x: i32, y: i32| {
+        max_range.is_none_or(|range| player.distance(x, y) <= range as f32)
+    };
+    let monster_at = |x: i32, y: i32| {
+        objects.iter().position(|object| {
+            object.pos() == (x, y) && object.fighter.is_some() && object.ai.is_some()
+        })
+    };
+
+    let mut cursor = player.pos();
+    while !tcod.backend.window_closed() {
+        // move cursor w/ arrows or mouse
+        let mut confirm = false;
+        match tcod.backend.check_for_event() {
+            Some(Event::Mouse(mouse)) => {
+                tcod.mouse = mouse;
+                tcod.key = Default::default();
+                if let Some(pos) = to_map(mouse.cx as i32, mouse.cy as i32, tcod.camera) {
+                    cursor = pos;
+                }
+                if mouse.rbutton_pressed {
+                    return None;
+                }
+                confirm = mouse.lbutton_pressed;
+            }
+            Some(Event::Key(key)) => {
+                tcod.key = key;
+                match key.code {
+                    Up => cursor.1 -= 1,
+                    Down => cursor.1 += 1,
+                    Left => cursor.0 -= 1,
+                    Right => cursor.0 += 1,
+                    Enter => confirm = true,
+                    Escape => return None,
+                    _ => {}
+                }
+            }
+            None => tcod.key = Default::default(),
+        }
+        // keep cursor on the map and on screen
+        let (camera_x, camera_y) = tcod.camera;
+        cursor.0 = cursor.0.clamp(0, MAP_WIDTH - 1).clamp(camera_x, camera_x + CAMERA_WIDTH - 1);
+        cursor.1 = cursor.1.clamp(0, MAP_HEIGHT - 1).clamp(camera_y, camera_y + CAMERA_HEIGHT - 1);
+
+        let (x, y) = cursor;
+        let target = monster_at(x, y);
+        let valid = tcod.fov.is_in_fov(x, y) && in_range(x, y) && (!monsters_only || target.is_some());
+        if confirm && valid {
+            return Some(match target {
+                Some(id) => Target::Object(id),
+                None => Target::Tile(x, y),
+            });
+        }
+
+        // draw game as usual
+        tcod.con.clear();
+        render_all(tcod, game, objects, false);
+
+        // shade range limit, mark targets, then cursor on top
+        let camera = tcod.camera;
+        for screen_y in 0..CAMERA_HEIGHT {
+            for screen_x in 0..CAMERA_WIDTH {
+                if let Some((x, y)) = to_map(screen_x, screen_y, camera) {
+                    if tcod.fov.is_in_fov(x, y) && in_range(x, y) {
+                        tcod.screen.set_char_background(screen_x, screen_y, DARK_GREY, BackgroundFlag::Lighten);
+                    }
+                }
+            }
+        }
+        for object in objects {
+            if object.ai.is_some()
+                && object.fighter.is_some()
+                && tcod.fov.is_in_fov(object.x, object.y)
+                && in_range(object.x, object.y)
+            {
+                if let Some((screen_x, screen_y)) = to_camera(object.x, object.y, camera) {
+                    tcod.screen.set_char_background(screen_x, screen_y, DARK_RED, BackgroundFlag::Set);
+                }
+            }
+        }
+        let cursor_color = if valid { LIGHT_GREEN } else { GREY };
+        if let Some((screen_x, screen_y)) = to_camera(x, y, camera) {
+            tcod.screen.set_char_background(screen_x, screen_y, cursor_color, BackgroundFlag::Set);
+        }
+
+        tcod.screen.set_default_foreground(WHITE);
+        tcod.screen.print_ex(
+            SCREEN_WIDTH / 2,
+            0,
+            BackgroundFlag::None,
+            TextAlignment::Center,
+            "Arrows/mouse to aim, Enter/left-click to confirm, Esc/right-click to cancel.",
+        );
+
+        tcod.backend.present(&tcod.screen);
+    }
+    None
+}
+
+// use item from inventory, returns true if it was used up
+fn use_item(inventory_id: usize, tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) -> bool {
+    use Item::*;
+    if let Some(item) = game.inventory[inventory_id].item {
+        let on_use = match item {
+            Heal => cast_heal,
+            Lightning => cast_lightning,
+            Confuse => cast_confuse,
+            Fireball => cast_fireball,
+            Stun => cast_stun,
+            Haste => cast_haste,
+            Regeneration => cast_regeneration,
+            Sword | Shield | Armor => toggle_equipment,
+        };
+        match on_use(inventory_id, tcod, game, objects) {
+            UseResult::UsedUp => {
+                // destroy after use
+                game.inventory.remove(inventory_id);
+                true
+            }
+            UseResult::UsedAndKept => true,
+            UseResult::Cancelled => {
+                game.messages.add("Cancelled", WHITE);
+                false
+            }
+        }
+    } else {
+        game.messages.add(
+            format!("The {} cannot be used.", game.inventory[inventory_id].name),
+            WHITE,
+        );
+        false
+    }
+}
+
+// equip item, or take it off if already worn
+fn toggle_equipment(inventory_id: usize, _tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) -> UseResult {
+    let equipment = match game.inventory[inventory_id].equipment {
+        Some(equipment) => equipment,
+        None => return UseResult::Cancelled,
+    };
+    if equipment.equipped {
+        game.inventory[inventory_id].dequip(&mut game.messages);
+        clamp_player_hp(game, objects);
+    } else {
+        // swap out whatever is in that slot already
+        if let Some(current) = get_equipped_in_slot(equipment.slot, &game.inventory) {
+            game.inventory[current].dequip(&mut game.messages);
+        }
+        game.inventory[inventory_id].equip(&mut game.messages);
+        clamp_player_hp(game, objects);
+    }
+    UseResult::UsedAndKept
+}
+
+// inventory index of item worn in given slot
+fn get_equipped_in_slot(slot: Slot, inventory: &[Object]) -> Option<usize> {
+    inventory.iter().position(|item| {
+        item.equipment
+            .is_some_and(|e| e.equipped && e.slot == slot)
+    })
+}
+
+// keeps player hp within max after equipment changes
+fn clamp_player_hp(game: &Game, objects: &mut [Object]) {
+    let max_hp = objects[PLAYER].max_hp(game);
+    if let Some(fighter) = objects[PLAYER].fighter.as_mut() {
+        fighter.hp = cmp::min(fighter.hp, max_hp);
+    }
+}
+
+fn cast_heal(_inventory_id: usize, _tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) -> UseResult {
+    // heal the player
+    if let Some(fighter) = objects[PLAYER].fighter {
+        if fighter.hp == objects[PLAYER].max_hp(game) {
+            game.messages.add("You are already at full health.", RED);
+            return UseResult::Cancelled;
+        }
+        game.messages.add("Your wounds start to feel better!", LIGHT_VIOLET);
+        objects[PLAYER].heal(HEAL_AMOUNT, game);
+        return UseResult::UsedUp;
+    }
+    UseResult::Cancelled
+}
+
+fn cast_lightning(_inventory_id: usize, tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) -> UseResult {
+    // find closest enemy inside max range and damage it
+    let monster_id = closest_monster(tcod, objects, LIGHTNING_RANGE);
+    if let Some(monster_id) = monster_id {
+        game.messages.add(
+            format!(
+                "A lightning bolt strikes the {} with a loud thunder! The damage is {} hit points.",
+                objects[monster_id].name, LIGHTNING_DAMAGE
+            ),
+            LIGHT_BLUE,
+        );
+        if let Some(xp) = objects[monster_id].take_damage(LIGHTNING_DAMAGE, game) {
+            objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+        }
+        UseResult::UsedUp
+    } else {
+        // no enemy found within max range
+        game.messages.add("No enemy is close enough to strike.", RED);
+        UseResult::Cancelled
+    }
+}
+
+fn cast_confuse(_inventory_id: usize, tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) -> UseResult {
+    // ask player for an enemy in range to confuse
+    game.messages.add("Choose an enemy to confuse.", LIGHT_CYAN);
+    let monster_id = match target_mode(tcod, game, objects, Some(CONFUSE_RANGE), true) {
+        Some(Target::Object(id)) => Some(id),
+        _ => None,
+    };
+    if let Some(monster_id) = monster_id {
+        let old_ai = objects[monster_id].ai.take().unwrap_or(Ai::Basic);
+        // replace monster's ai w/ a confused one, old one comes back after some turns
+        objects[monster_id].ai = Some(Ai::Confused {
+            previous_ai: Box::new(old_ai),
+            num_turns: CONFUSE_NUM_TURNS,
+        });
+        game.messages.add(
+            format!(
+                "The eyes of {} look vacant, as it starts to stumble around!",
+                objects[monster_id].name
+            ),
+            LIGHT_GREEN,
+        );
+        UseResult::UsedUp
+    } else {
+        UseResult::Cancelled
+    }
+}
+
+fn cast_stun(_inventory_id: usize, tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) -> UseResult {
+    // ask player for an enemy in range to stun
+    game.messages.add("Choose an enemy to tie up in red tape.", LIGHT_CYAN);
+    match target_mode(tcod, game, objects, Some(STUN_RANGE), true) {
+        Some(Target::Object(monster_id)) => {
+            let effect = Effect {
+                kind: EffectKind::Stun,
+                turns: STUN_NUM_TURNS,
+                strength: 0,
+            };
+            objects[monster_id].add_effect(effect, &mut game.messages);
+            UseResult::UsedUp
+        }
+        _ => UseResult::Cancelled,
+    }
+}
+
+fn cast_haste(_inventory_id: usize, _tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) -> UseResult {
+    let effect = Effect {
+        kind: EffectKind::Haste,
+        turns: HASTE_NUM_TURNS,
+        strength: 0,
+    };
+    objects[PLAYER].add_effect(effect, &mut game.messages);
+    UseResult::UsedUp
+}
+
+fn cast_regeneration(_inventory_id: usize, _tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) -> UseResult {
+    let effect = Effect {
+        kind: EffectKind::Regeneration,
+        turns: REGEN_NUM_TURNS,
+        strength: REGEN_AMOUNT,
+    };
+    objects[PLAYER].add_effect(effect, &mut game.messages);
+    UseResult::UsedUp
+}
+
+fn cast_fireball(_inventory_id: usize, tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) -> UseResult {
+    // ask player for a tile to throw the fireball at
+    game.messages.add("Choose where to throw the fireball.", LIGHT_CYAN);
+    let (x, y) = match target_mode(tcod, game, objects, Some(FIREBALL_RANGE), false) {
+        Some(Target::Tile(x, y)) => (x, y),
+        Some(Target::Object(id)) => objects[id].pos(),
+        None => return UseResult::Cancelled,
+    };
+    game.messages.add(
+        format!(
+            "The fireball explodes, burning everything within {} tiles!",
+            FIREBALL_RADIUS
+        ),
+        ORANGE,
+    );
+
+    // burn every fighter in radius, player included
+    let mut xp_to_gain = 0;
+    for (id, obj) in objects.iter_mut().enumerate() {
+        if obj.distance(x, y) <= FIREBALL_RADIUS as f32 && obj.fighter.is_some() {
+            game.messages.add(
+                format!(
+                    "The {} gets burned for {} hit points.",
+                    obj.name, FIREBALL_DAMAGE
+                ),
+                ORANGE,
+            );
+            if let Some(xp) = obj.take_damage(FIREBALL_DAMAGE, game) {
+                // don't reward player for burning themself
+                if id != PLAYER {
+                    xp_to_gain += xp;
+                }
+            }
         }
     }
+    if let Some(fighter) = objects[PLAYER].fighter.as_mut() {
+        fighter.xp += xp_to_gain;
+    }
+
+    UseResult::UsedUp
 }
 
 
 
+// returns value for given level from table sorted by level
+fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
+    table
+        .iter()
+        .rev()
+        .find(|transition| level >= transition.level)
+        .map_or(0, |transition| transition.value)
+}
+
 // creates monsters!! 
-fn place_objects(room: Rect, map: &Map, objects: &mut Vec<Object>) {
+// spots are the tiles to pick from, usually a room's inside
+fn place_objects(
+    spots: &[(i32, i32)],
+    map: &Map,
+    objects: &mut Vec<Object>,
+    level: u32,
+    monsters: &[MonsterTemplate],
+    rng: &mut GameRng,
+) {
+    // max monsters per rm, grows w/ depth
+    let max_monsters = from_dungeon_level(
+        &[
+            Transition { level: 1, value: 3 },
+            Transition { level: 4, value: 4 },
+            Transition { level: 6, value: 5 },
+        ],
+        level,
+    );
+
+    // monsters allowed this deep, weighted by template
+    let monster_chances = &mut monsters
+        .iter()
+        .enumerate()
+        .map(|(index, template)| Weighted {
+            weight: from_dungeon_level(&template.spawn_weight, level),
+            item: index,
+        })
+        .filter(|chance| chance.weight > 0)
+        .collect::<Vec<_>>();
+
     // chooses rand no. monsters
-    let num_monsters = rand::thread_rng().gen_range(0, MAX_ROOM_MONSTERS + 1);
+    let num_monsters = if monster_chances.is_empty() || spots.is_empty() {
+        0
+    } else {
+        rng.gen_range(0, max_monsters + 1)
+    };
+    let monster_choice = WeightedChoice::new(monster_chances);
 
     for _ in 0..num_monsters{
         // choose rand loc. for monster
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
-
-        let mut monster = if rand::random::<f32>() < 0.8 {
-            // 80% chance of getting bezos (orc)
-            // create bezos
-            let mut bezos = Object::new(x, y, 'b', "bezos", BLACK, true);
-            bezos.fighter = Some(Fighter {
-                max_hp: 10,
-                hp: 10,
-                defense: 0,
-                power: 3,
-                on_death: DeathCallback::Monster,
-            });
-            bezos.ai = Some(Ai::Basic);
-            bezos
-        } else {
-            // 20% for trump (troll)
-            let mut trump = Object::new(x, y, 'T', "trump", BLACK, true);
-            trump.fighter = Some(Fighter {
-                max_hp: 16,
-                hp: 16,
-                defense: 1,
-                power: 4,
-                on_death: DeathCallback::Monster,
-            });
-            trump.ai = Some(Ai::Basic);
-            trump
-        };
+        let (x, y) = spots[rng.gen_range(0, spots.len())];
+
+        let template = &monsters[monster_choice.ind_sample(rng)];
+        if !is_blocked(x, y, map, objects) {
+            objects.push(template.spawn(x, y));
+        }
+    }
+
+    // item weights, stronger ones show up deeper
+    let item_chances = &mut [
+        Weighted {
+            weight: 35,
+            item: Item::Heal,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition { level: 4, value: 25 }], level),
+            item: Item::Lightning,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition { level: 6, value: 25 }], level),
+            item: Item::Fireball,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition { level: 2, value: 10 }], level),
+            item: Item::Confuse,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition { level: 2, value: 10 }], level),
+            item: Item::Stun,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition { level: 3, value: 10 }], level),
+            item: Item::Haste,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition { level: 2, value: 15 }], level),
+            item: Item::Regeneration,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition { level: 4, value: 5 }], level),
+            item: Item::Sword,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition { level: 8, value: 15 }], level),
+            item: Item::Shield,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition { level: 6, value: 10 }], level),
+            item: Item::Armor,
+        },
+    ];
+    let item_choice = WeightedChoice::new(item_chances);
+
+    // chooses rand no. items
+    let num_items = if spots.is_empty() {
+        0
+    } else {
+        rng.gen_range(0, MAX_ROOM_ITEMS + 1)
+    };
+
+    for _ in 0..num_items {
+        // choose rand loc. for item
+        let (x, y) = spots[rng.gen_range(0, spots.len())];
+
+        // only place it if tile is not blocked
         if !is_blocked(x, y, map, objects) {
-            monster.alive = true;
-            objects.push(monster);
+            let mut object = match item_choice.ind_sample(rng) {
+                Item::Heal => {
+                    // create healing potion
+                    let mut object = Object::new(x, y, '!', "healing potion", VIOLET, false);
+                    object.item = Some(Item::Heal);
+                    object
+                }
+                Item::Lightning => {
+                    // create lightning bolt scroll
+                    let mut object =
+                        Object::new(x, y, '#', "scroll of lightning bolt", LIGHT_YELLOW, false);
+                    object.item = Some(Item::Lightning);
+                    object
+                }
+                Item::Fireball => {
+                    // create fireball scroll
+                    let mut object =
+                        Object::new(x, y, '#', "scroll of fireball", LIGHT_YELLOW, false);
+                    object.item = Some(Item::Fireball);
+                    object
+                }
+                Item::Confuse => {
+                    // create confuse scroll
+                    let mut object =
+                        Object::new(x, y, '#', "scroll of confusion", LIGHT_YELLOW, false);
+                    object.item = Some(Item::Confuse);
+                    object
+                }
+                Item::Stun => {
+                    // create stun scroll
+                    let mut object =
+                        Object::new(x, y, '#', "scroll of red tape", LIGHT_YELLOW, false);
+                    object.item = Some(Item::Stun);
+                    object
+                }
+                Item::Haste => {
+                    // create haste potion
+                    let mut object = Object::new(x, y, '!', "energy drink", LIGHT_BLUE, false);
+                    object.item = Some(Item::Haste);
+                    object
+                }
+                Item::Regeneration => {
+                    // create regeneration potion
+                    let mut object =
+                        Object::new(x, y, '!', "potion of regeneration", LIGHT_RED, false);
+                    object.item = Some(Item::Regeneration);
+                    object
+                }
+                Item::Sword => {
+                    // create a sword
+                    let mut object = Object::new(x, y, '/', "sword", SKY, false);
+                    object.item = Some(Item::Sword);
+                    object.equipment = Some(Equipment {
+                        slot: Slot::RightHand,
+                        equipped: false,
+                        power_bonus: 3,
+                        defense_bonus: 0,
+                        max_hp_bonus: 0,
+                    });
+                    object
+                }
+                Item::Shield => {
+                    // create a shield
+                    let mut object = Object::new(x, y, '[', "shield", DARKER_ORANGE, false);
+                    object.item = Some(Item::Shield);
+                    object.equipment = Some(Equipment {
+                        slot: Slot::LeftHand,
+                        equipped: false,
+                        power_bonus: 0,
+                        defense_bonus: 1,
+                        max_hp_bonus: 0,
+                    });
+                    object
+                }
+                Item::Armor => {
+                    // create a kevlar vest
+                    let mut object = Object::new(x, y, ']', "kevlar vest", DARK_SEPIA, false);
+                    object.item = Some(Item::Armor);
+                    object.equipment = Some(Equipment {
+                        slot: Slot::Body,
+                        equipped: false,
+                        power_bonus: 0,
+                        defense_bonus: 1,
+                        max_hp_bonus: 10,
+                    });
+                    object
+                }
+            };
+            object.always_visible = true;
+            objects.push(object);
         }
     }
 }
 
+// names of objects under the mouse that are in fov
+fn get_names_under_mouse(mouse: Mouse, objects: &[Object], fov_map: &FovMap, camera: (i32, i32)) -> String {
+    let (x, y) = match to_map(mouse.cx as i32, mouse.cy as i32, camera) {
+        Some(pos) => pos,
+        None => return String::new(),
+    };
+
+    // list of names of all objects at mouse coords and in fov
+    let names = objects
+        .iter()
+        .filter(|obj| obj.pos() == (x, y) && fov_map.is_in_fov(obj.x, obj.y))
+        .map(|obj| obj.name.clone())
+        .collect::<Vec<_>>();
+
+    // join names, separated by commas
+    names.join(", ")
+}
+
 fn render_bar(
     panel: &mut Offscreen,
     x: i32,
@@ -507,33 +2277,34 @@ fn render_bar(
     );
 }
 
-// fill map 
-fn make_map(objects: &mut Vec<Object>) -> Map {
-    // fills map with blocked tiles
-    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
-
-    // pillars for testing map
-    /* map[30][22] = Tile::wall();
-       map[50][22] = Tile::wall(); */
-
-    // create two rooms for testing
-    /* let room1 = Rect::new(20, 15, 10, 15);
-       let room2 = Rect::new(50, 15, 10, 15);
-       create_room(room1, &mut map);
-       create_room(room2, &mut map);
-       create_h_tunnel(25, 55, 23, &mut map);*/
+// joins two rooms w/ an L-shaped tunnel between their centers
+fn connect_rooms(first: Rect, second: Rect, map: &mut Map, rng: &mut GameRng) {
+    let (prev_x, prev_y) = first.center();
+    let (new_x, new_y) = second.center();
 
+    //toss a coin (random boolean)
+    if rng.gen() {
+        // first move horizontally, then vertically
+        create_h_tunnel(prev_x, new_x, prev_y, map);
+        create_v_tunnel(prev_y, new_y, new_x, map);
+    } else {
+        // first move vert then hor
+        create_v_tunnel(prev_y, new_y, prev_x, map);
+        create_h_tunnel(prev_x, new_x, new_y, map);
+    }
+}
 
-    // generate rooms
-    let mut rooms = vec![];
+// random non-overlapping rooms, each joined to the one before it
+fn make_random_rooms(map: &mut Map, rng: &mut GameRng) -> Vec<Rect> {
+    let mut rooms: Vec<Rect> = vec![];
 
     for _ in 0..MAX_ROOMS {
         // random width and height
-        let w = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-        let h = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+        let w = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+        let h = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
         // random position within map
-        let x = rand::thread_rng().gen_range(0, MAP_WIDTH - w);
-        let y = rand::thread_rng().gen_range(0, MAP_HEIGHT - h);
+        let x = rng.gen_range(0, MAP_WIDTH - w);
+        let y = rng.gen_range(0, MAP_HEIGHT - h);
 
         let new_room = Rect::new(x, y, w, h);
 
@@ -546,34 +2317,11 @@ fn make_map(objects: &mut Vec<Object>) -> Map {
             // no intersections for this room
 
             // paint it to tiles
-            create_room(new_room, &mut map);
-
-            // add some content (ie monsters) to room
-            place_objects(new_room, &map, objects);
-
-            // center coords of new room
-            let (new_x, new_y) = new_room.center();
-
-            // this is the first room, where player starts
-            if rooms.is_empty() {
-                objects[PLAYER].set_pos(new_x, new_y);
-            } else {
-                // for all rooms after the first
-                // connect to previous room with a tunnel
+            create_room(new_room, map);
 
-                // center coords of prev room
-                let (prev_x, prev_y) = rooms[rooms.len() - 1].center();
-
-                //toss a coin (random boolean)
-                if rand::random() {
-                    // first move horizontally, then vertically
-                    create_h_tunnel(prev_x, new_x, prev_y, &mut map);
-                    create_v_tunnel(prev_y, new_y, new_x, &mut map);
-                } else {
-                    // first move vert then hor
-                    create_v_tunnel(prev_y, new_y, prev_x, &mut map);
-                    create_h_tunnel(prev_x, new_x, new_y, &mut map);
-                }
+            // connect to previous room with a tunnel
+            if let Some(&prev_room) = rooms.last() {
+                connect_rooms(prev_room, new_room, map, rng);
             }
 
             // append new room to list
@@ -581,33 +2329,418 @@ fn make_map(objects: &mut Vec<Object>) -> Map {
         }
     }
 
-    map
-    
+    rooms
 }
 
-// draws all objects in list
-fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_recompute: bool){
-    let mut to_draw: Vec<_> = objects
-        .iter()
-        .filter(|o| tcod.fov.is_in_fov(o.x, o.y))
-        .collect();
-    // sort so nonblocking objs come first
-    to_draw.sort_by(|o1, o2| { o1.blocks.cmp(&o2.blocks)});
-    // draw objects in list
-    for object in &to_draw {
-            object.draw(&mut tcod.con);
-    }
-
-    // recomputes fov if needed (player move)
+// binary space partition: split the map into leaves, one room per leaf
+fn make_bsp_rooms(map: &mut Map, rng: &mut GameRng) -> Vec<Rect> {
+    let mut rooms = vec![];
+    let whole_map = Rect {
+        x1: 0,
+        y1: 0,
+        x2: MAP_WIDTH - 1,
+        y2: MAP_HEIGHT - 1,
+    };
+    bsp_split(whole_map, BSP_DEPTH, map, &mut rooms, rng);
+    rooms
+}
+
+// recursively splits area in two, places a room in each leaf and
+// joins sibling halves. returns a room of this subtree to connect thru
+fn bsp_split(area: Rect, depth: u32, map: &mut Map, rooms: &mut Vec<Rect>, rng: &mut GameRng) -> Rect {
+    let w = area.x2 - area.x1;
+    let h = area.y2 - area.y1;
+    let can_split_vertically = w >= 2 * BSP_MIN_LEAF_SIZE;
+    let can_split_horizontally = h >= 2 * BSP_MIN_LEAF_SIZE;
+
+    if depth == 0 || !(can_split_vertically || can_split_horizontally) {
+        // leaf, room of random size somewhere inside
+        let room_w = rng.gen_range(ROOM_MIN_SIZE, cmp::min(ROOM_MAX_SIZE, w) + 1);
+        let room_h = rng.gen_range(ROOM_MIN_SIZE, cmp::min(ROOM_MAX_SIZE, h) + 1);
+        let x = rng.gen_range(area.x1, area.x2 - room_w + 1);
+        let y = rng.gen_range(area.y1, area.y2 - room_h + 1);
+        let room = Rect::new(x, y, room_w, room_h);
+        create_room(room, map);
+        rooms.push(room);
+        return room;
+    }
+
+    // split across the longer side when both work
+    let vertical = if can_split_vertically && can_split_horizontally {
+        w >= h
+    } else {
+        can_split_vertically
+    };
+    let (first, second) = if vertical {
+        let split = rng.gen_range(area.x1 + BSP_MIN_LEAF_SIZE, area.x2 - BSP_MIN_LEAF_SIZE + 1);
+        (Rect { x2: split, ..area }, Rect { x1: split, ..area })
+    } else {
+        let split = rng.gen_range(area.y1 + BSP_MIN_LEAF_SIZE, area.y2 - BSP_MIN_LEAF_SIZE + 1);
+        (Rect { y2: split, ..area }, Rect { y1: split, ..area })
+    };
+
+    let first_room = bsp_split(first, depth - 1, map, rooms, rng);
+    let second_room = bsp_split(second, depth - 1, map, rooms, rng);
+    connect_rooms(first_room, second_room, map, rng);
+
+    // either side works for joining further up
+    if rng.gen() {
+        first_room
+    } else {
+        second_room
+    }
+}
+
+// tiles reachable from start w/o crossing walls (4-way), start included
+// in order of distance, so the last one is as far away as it gets
+fn flood_fill(map: &Map, start: (i32, i32)) -> Vec<(i32, i32)> {
+    let mut seen = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    let mut reached = vec![];
+    let mut queue = VecDeque::new();
+    if map[start.0 as usize][start.1 as usize].blocked {
+        return reached;
+    }
+    seen[start.0 as usize][start.1 as usize] = true;
+    queue.push_back(start);
+
+    while let Some((x, y)) = queue.pop_front() {
+        reached.push((x, y));
+        for &(dx, dy) in &[(0, -1), (0, 1), (-1, 0), (1, 0)] {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || ny < 0 || nx >= MAP_WIDTH || ny >= MAP_HEIGHT {
+                continue;
+            }
+            if !seen[nx as usize][ny as usize] && !map[nx as usize][ny as usize].blocked {
+                seen[nx as usize][ny as usize] = true;
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+    reached
+}
+
+// cellular automata cave: random noise smoothed into blobs, only the
+// biggest connected pocket is kept. returns its floor tiles
+fn make_caves(map: &mut Map, rng: &mut GameRng) -> Vec<(i32, i32)> {
+    let (width, height) = (MAP_WIDTH as usize, MAP_HEIGHT as usize);
+    loop {
+        // random walls, border is always wall
+        let mut walls = vec![vec![true; height]; width];
+        for column in walls.iter_mut().take(width - 1).skip(1) {
+            for wall in column.iter_mut().take(height - 1).skip(1) {
+                *wall = rng.gen::<f32>() < CAVE_WALL_CHANCE;
+            }
+        }
+
+        // smooth: tile becomes wall if 5+ of the 9 around it (itself too) are
+        for _ in 0..CAVE_SMOOTHING_STEPS {
+            let previous = walls.clone();
+            for (x, column) in walls.iter_mut().enumerate().take(width - 1).skip(1) {
+                for (y, wall) in column.iter_mut().enumerate().take(height - 1).skip(1) {
+                    let neighbours = (x - 1..=x + 1)
+                        .flat_map(|nx| (y - 1..=y + 1).map(move |ny| (nx, ny)))
+                        .filter(|&(nx, ny)| previous[nx][ny])
+                        .count();
+                    *wall = neighbours >= 5;
+                }
+            }
+        }
+
+        for x in 0..width {
+            for y in 0..height {
+                map[x][y] = if walls[x][y] { Tile::wall() } else { Tile::empty() };
+            }
+        }
+
+        // find biggest pocket
+        let mut seen = vec![vec![false; height]; width];
+        let mut largest: Vec<(i32, i32)> = vec![];
+        for x in 0..MAP_WIDTH {
+            for y in 0..MAP_HEIGHT {
+                if walls[x as usize][y as usize] || seen[x as usize][y as usize] {
+                    continue;
+                }
+                let pocket = flood_fill(map, (x, y));
+                for &(px, py) in &pocket {
+                    seen[px as usize][py as usize] = true;
+                }
+                if pocket.len() > largest.len() {
+                    largest = pocket;
+                }
+            }
+        }
+
+        // try again if the cave came out too small to play
+        if largest.len() < CAVE_MIN_FLOOR {
+            continue;
+        }
+
+        // fill in every other pocket
+        let mut keep = vec![vec![false; height]; width];
+        for &(x, y) in &largest {
+            keep[x as usize][y as usize] = true;
+        }
+        for x in 0..width {
+            for y in 0..height {
+                if !keep[x][y] {
+                    map[x][y] = Tile::wall();
+                }
+            }
+        }
+        return largest;
+    }
+}
+
+// caves have no rooms, so pick some floor tiles and use what's around them
+fn cave_spawn_areas(floor: &[(i32, i32)], rng: &mut GameRng) -> Vec<Vec<(i32, i32)>> {
+    (0..CAVE_SPAWN_AREAS)
+        .map(|_| {
+            let (cx, cy) = floor[rng.gen_range(0, floor.len())];
+            floor
+                .iter()
+                .cloned()
+                .filter(|&(x, y)| {
+                    (x - cx).abs() <= CAVE_SPAWN_RADIUS && (y - cy).abs() <= CAVE_SPAWN_RADIUS
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// what validate_map found wrong with a level
+#[derive(Debug, Default)]
+struct MapReport {
+    // floor regions the player can't walk to
+    unreachable: Vec<Vec<(i32, i32)>>,
+    // indices of objects stuck in a wall or out of reach
+    blocked_spawns: Vec<usize>,
+}
+
+impl MapReport {
+    pub fn is_ok(&self) -> bool {
+        self.unreachable.is_empty() && self.blocked_spawns.is_empty()
+    }
+}
+
+// checks every floor tile and every object can be reached from the player
+fn validate_map(map: &Map, objects: &[Object]) -> MapReport {
+    let mut report = MapReport::default();
+    let mut seen = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    for (x, y) in flood_fill(map, objects[PLAYER].pos()) {
+        seen[x as usize][y as usize] = true;
+    }
+    let reachable = seen.clone();
+
+    // whatever floor is left over is cut off, group it into regions
+    for x in 0..MAP_WIDTH {
+        for y in 0..MAP_HEIGHT {
+            if map[x as usize][y as usize].blocked || seen[x as usize][y as usize] {
+                continue;
+            }
+            let region = flood_fill(map, (x, y));
+            for &(rx, ry) in &region {
+                seen[rx as usize][ry as usize] = true;
+            }
+            report.unreachable.push(region);
+        }
+    }
+
+    for (id, object) in objects.iter().enumerate().skip(1) {
+        let (x, y) = object.pos();
+        let outside = x < 0 || y < 0 || x >= MAP_WIDTH || y >= MAP_HEIGHT;
+        if outside || !reachable[x as usize][y as usize] {
+            report.blocked_spawns.push(id);
+        }
+    }
+    report
+}
+
+// tunnels each cut off region to the closest reachable floor and drops
+// objects that are still stuck afterwards. stairs are never dropped,
+// the level has to be rerolled then
+fn repair_map(map: &mut Map, objects: &mut Vec<Object>, report: &MapReport, rng: &mut GameRng) {
+    for region in &report.unreachable {
+        let reachable = flood_fill(map, objects[PLAYER].pos());
+        // already joined up by an earlier tunnel
+        if reachable.contains(&region[0]) {
+            continue;
+        }
+        let from = region[rng.gen_range(0, region.len())];
+        let to = reachable
+            .iter()
+            .cloned()
+            .min_by_key(|&(x, y)| (x - from.0).abs() + (y - from.1).abs())
+            .unwrap_or_else(|| objects[PLAYER].pos());
+        if rng.gen() {
+            create_h_tunnel(from.0, to.0, from.1, map);
+            create_v_tunnel(from.1, to.1, to.0, map);
+        } else {
+            create_v_tunnel(from.1, to.1, from.0, map);
+            create_h_tunnel(from.0, to.0, to.1, map);
+        }
+    }
+
+    // anything still stuck goes, back to front so ids stay valid
+    let mut stuck = validate_map(map, objects).blocked_spawns;
+    stuck.retain(|&id| !objects[id].is_stairs());
+    for &id in stuck.iter().rev() {
+        objects.remove(id);
+    }
+}
+
+// fill map, checked so everything on it can be reached
+fn make_map(
+    objects: &mut Vec<Object>,
+    level: u32,
+    generator: MapGenerator,
+    monsters: &[MonsterTemplate],
+    rng: &mut GameRng,
+) -> Map {
+    loop {
+        let mut map = generate_map(objects, level, generator, monsters, rng);
+        let report = validate_map(&map, objects);
+        if report.is_ok() {
+            return map;
+        }
+        repair_map(&mut map, objects, &report, rng);
+        // if the repair didn't take, throw it away and start over
+        if validate_map(&map, objects).is_ok() {
+            return map;
+        }
+    }
+}
+
+// one attempt at a level, may come out broken
+fn generate_map(
+    objects: &mut Vec<Object>,
+    level: u32,
+    generator: MapGenerator,
+    monsters: &[MonsterTemplate],
+    rng: &mut GameRng,
+) -> Map {
+    // fills map with blocked tiles
+    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+
+    // pillars for testing map
+    /* map[30][22] = Tile::wall();
+       map[50][22] = Tile::wall(); */
+
+    // create two rooms for testing
+    /* let room1 = Rect::new(20, 15, 10, 15);
+       let room2 = Rect::new(50, 15, 10, 15);
+       create_room(room1, &mut map);
+       create_room(room2, &mut map);
+       create_h_tunnel(25, 55, 23, &mut map);*/
+
+
+    // drop everything but the player from previous level
+    objects.truncate(1);
+
+    // carve level w/ chosen generator, find where player starts,
+    // where stairs go and which groups of tiles get content
+    let (start, stairs_pos, spawn_areas) = match generator {
+        MapGenerator::Rooms | MapGenerator::Bsp => {
+            let rooms = if generator == MapGenerator::Rooms {
+                make_random_rooms(&mut map, rng)
+            } else {
+                make_bsp_rooms(&mut map, rng)
+            };
+            // player in first room, stairs in last
+            let start = rooms[0].center();
+            let stairs_pos = rooms[rooms.len() - 1].center();
+            let spawn_areas: Vec<_> = rooms.iter().map(|room| room.tiles()).collect();
+            (start, stairs_pos, spawn_areas)
+        }
+        MapGenerator::Caves => {
+            let floor = make_caves(&mut map, rng);
+            // stairs as far from the start as the cave allows
+            let start = floor[rng.gen_range(0, floor.len())];
+            let reachable = flood_fill(&map, start);
+            let stairs_pos = reachable[reachable.len() - 1];
+            let spawn_areas = cave_spawn_areas(&floor, rng);
+            (start, stairs_pos, spawn_areas)
+        }
+    };
+
+    objects[PLAYER].set_pos(start.0, start.1);
+
+    // add some content (ie monsters) to each area
+    for spots in &spawn_areas {
+        place_objects(spots, &map, objects, level, monsters, rng);
+    }
+
+    let mut stairs = Object::new(stairs_pos.0, stairs_pos.1, '>', "stairs", WHITE, false);
+    stairs.always_visible = true;
+    objects.push(stairs);
+
+    map
+    
+}
+
+// top left corner of a camera centered on target, kept inside the map
+fn camera_origin(target: (i32, i32)) -> (i32, i32) {
+    let x = (target.0 - CAMERA_WIDTH / 2).clamp(0, cmp::max(MAP_WIDTH - CAMERA_WIDTH, 0));
+    let y = (target.1 - CAMERA_HEIGHT / 2).clamp(0, cmp::max(MAP_HEIGHT - CAMERA_HEIGHT, 0));
+    (x, y)
+}
+
+// map coords to screen coords, None if the camera doesn't show them
+fn to_camera(x: i32, y: i32, camera: (i32, i32)) -> Option<(i32, i32)> {
+    let (x, y) = (x - camera.0, y - camera.1);
+    if x >= 0 && y >= 0 && x < CAMERA_WIDTH && y < CAMERA_HEIGHT {
+        Some((x, y))
+    } else {
+        None
+    }
+}
+
+// screen coords to map coords, None if not over the map
+fn to_map(x: i32, y: i32, camera: (i32, i32)) -> Option<(i32, i32)> {
+    if x < 0 || y < 0 || x >= CAMERA_WIDTH || y >= CAMERA_HEIGHT {
+        return None;
+    }
+    let (x, y) = (x + camera.0, y + camera.1);
+    if x < MAP_WIDTH && y < MAP_HEIGHT {
+        Some((x, y))
+    } else {
+        None
+    }
+}
+
+// draws all objects in list
+fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_recompute: bool){
+    // camera follows the player
+    tcod.camera = camera_origin(objects[PLAYER].pos());
+    let camera = tcod.camera;
+
+    let mut to_draw: Vec<_> = objects
+        .iter()
+        .filter(|o| {
+            tcod.fov.is_in_fov(o.x, o.y)
+                || (o.always_visible && game.map[o.x as usize][o.y as usize].explored)
+        })
+        .collect();
+    // sort so nonblocking objs come first
+    to_draw.sort_by(|o1, o2| { o1.blocks.cmp(&o2.blocks)});
+    // draw objects in list
+    for object in &to_draw {
+            object.draw(&mut tcod.con, camera);
+    }
+
+    // recomputes fov if needed (player move)
     if fov_recompute {
         let player = &objects[PLAYER];
         tcod.fov
             .compute_fov(player.x, player.y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
     }
 
-    // set bg color for tiles
-    for y in 0..MAP_HEIGHT {
-        for x in 0..MAP_WIDTH {
+    // set bg color for tiles the camera sees
+    for screen_y in 0..CAMERA_HEIGHT {
+        for screen_x in 0..CAMERA_WIDTH {
+            let (x, y) = match to_map(screen_x, screen_y, camera) {
+                Some(pos) => pos,
+                None => continue,
+            };
             let visible = tcod.fov.is_in_fov(x, y);
             let wall = game.map[x as usize][y as usize].block_sight;
 
@@ -638,7 +2771,7 @@ fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_recomput
             // show explored tiles only
             if *explored {
                 tcod.con
-                    .set_char_background(x, y, color, BackgroundFlag::Set);
+                    .set_char_background(screen_x, screen_y, color, BackgroundFlag::Set);
             }
         }
 
@@ -655,16 +2788,16 @@ fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_recomput
             SCREEN_HEIGHT - 2,
             BackgroundFlag::None,
             TextAlignment::Left,
-            format!("HP: {}/{} ", fighter.hp, fighter.max_hp),
+            format!("HP: {}/{} ", fighter.hp, fighter.base_max_hp),
         );
     }*/ 
 
-    // blit contents of con to the root console & present it
+    // blit contents of con to the screen
     blit (
         &tcod.con,
         (0, 0),
-        (MAP_WIDTH, MAP_HEIGHT),
-        &mut tcod.root,
+        (CAMERA_WIDTH, CAMERA_HEIGHT),
+        &mut tcod.screen,
         (0, 0),
         1.0,
         1.0,
@@ -675,18 +2808,29 @@ fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_recomput
     tcod.panel.clear();
 
     let mut y = MSG_HEIGHT as i32;
-    for &(ref msg, color) in game.messages.iter().rev() {
-        let msg_height = tcod.panel.get_height_rect(MSG_X, y, MSG_WIDTH, 0, msg);
+    for message in game.messages.iter().rev() {
+        let msg = message.line();
+        let msg_height = tcod.panel.get_height_rect(MSG_X, y, MSG_WIDTH, 0, &msg);
         y -= msg_height;
         if y < 0 {
             break;
         }
-        tcod.panel.set_default_foreground(color);
+        tcod.panel.set_default_foreground(message.color);
         tcod.panel.print_rect(MSG_X, y, MSG_WIDTH, 0, msg);
     }
 
+    // show names of objects under mouse
+    tcod.panel.set_default_foreground(LIGHT_GREY);
+    tcod.panel.print_ex(
+        1,
+        0,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        get_names_under_mouse(tcod.mouse, objects, &tcod.fov, camera),
+    );
+
     let hp = objects[PLAYER].fighter.map_or(0, |f| f.hp);
-    let max_hp = objects[PLAYER].fighter.map_or(0, |f| f.max_hp);
+    let max_hp = objects[PLAYER].max_hp(game);
     render_bar (
         &mut tcod.panel,
         1,
@@ -699,12 +2843,50 @@ fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_recomput
         DARKER_RED,
     );
 
+    let level = objects[PLAYER].fighter.map_or(1, |f| f.level);
+    let xp = objects[PLAYER].fighter.map_or(0, |f| f.xp);
+    render_bar (
+        &mut tcod.panel,
+        1,
+        2,
+        BAR_WIDTH,
+        "XP",
+        xp,
+        level_up_xp(level),
+        LIGHT_VIOLET,
+        DARKER_VIOLET,
+    );
+
+    tcod.panel.set_default_foreground(WHITE);
+    tcod.panel.print_ex(
+        1,
+        3,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        format!("Dungeon level: {}", game.dungeon_level),
+    );
+    tcod.panel.print_ex(
+        1,
+        4,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        format!("Seed: {}", game.seed),
+    );
+
+    // status tags under the bars
+    let mut x = 1;
+    for effect in &objects[PLAYER].effects {
+        tcod.panel.set_default_foreground(effect.kind.color());
+        tcod.panel.print_ex(x, 5, BackgroundFlag::None, TextAlignment::Left, effect.kind.tag());
+        x += effect.kind.tag().len() as i32 + 1;
+    }
+
 
     blit(
         &tcod.panel,
         (0,0),
         (SCREEN_WIDTH,PANEL_HEIGHT),
-        &mut tcod.root,
+        &mut tcod.screen,
         (0, PANEL_Y),
         1.0,
         1.0
@@ -713,89 +2895,614 @@ fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_recomput
 
 
 
+}
+
+// shows a window with lettered options, returns index of the chosen one
+fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, tcod: &mut Tcod) -> Option<usize> {
+    assert!(
+        options.len() <= INVENTORY_SIZE,
+        "Cannot have a menu with more than 26 options."
+    );
+
+    // total height for header (after auto-wrap) and one line per option
+    let header_height = tcod.screen.get_height_rect(0, 0, width, SCREEN_HEIGHT, header);
+    let height = options.len() as i32 + header_height;
+
+    // offscreen console for the menu window
+    let mut window = Offscreen::new(width, height);
+
+    // print header with auto-wrap
+    window.set_default_foreground(WHITE);
+    window.print_rect_ex(
+        0,
+        0,
+        width,
+        height,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        header,
+    );
+
+    // print all options
+    for (index, option_text) in options.iter().enumerate() {
+        let menu_letter = (b'a' + index as u8) as char;
+        let text = format!("({}) {}", menu_letter, option_text.as_ref());
+        window.print_ex(
+            0,
+            header_height + index as i32,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            text,
+        );
+    }
+
+    // blit window to center of screen
+    let x = SCREEN_WIDTH / 2 - width / 2;
+    let y = SCREEN_HEIGHT / 2 - height / 2;
+    blit(&window, (0, 0), (width, height), &mut tcod.screen, (x, y), 1.0, 0.7);
+
+    // present screen and wait for key
+    tcod.backend.present(&tcod.screen);
+    let key = tcod.backend.wait_for_keypress();
+
+    // convert ascii code to index, return it if it matches an option
+    if key.printable.is_alphabetic() {
+        let index = key.printable.to_ascii_lowercase() as usize - 'a' as usize;
+        if index < options.len() {
+            Some(index)
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
+
+// menu w/o options, just shows some text until a key is pressed
+fn msgbox(text: &str, width: i32, tcod: &mut Tcod) {
+    let options: &[&str] = &[];
+    menu(text, options, width, tcod);
+}
+
+// splits text into lines no longer than width, breaking between words.
+// words too long for a line of their own get cut up
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let width = cmp::max(width, 1);
+    let mut lines = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        if !line.is_empty() && line.chars().count() + 1 + word.len() > width {
+            lines.push(line);
+            line = String::new();
+        }
+        while word.len() > width {
+            lines.push(word.drain(..width).collect());
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.extend(word);
+    }
+    lines.push(line);
+    lines
+}
+
+// closest line before `before` containing query, wrapping round to the end
+fn find_line(lines: &[(String, Color)], query: &str, before: usize) -> Option<usize> {
+    let query = query.to_lowercase();
+    let matches = |i: &usize| lines[*i].0.to_lowercase().contains(&query);
+    (0..before)
+        .rev()
+        .find(matches)
+        .or_else(|| (before..lines.len()).rev().find(matches))
+}
+
+// full screen message history. arrows and page up/down scroll, "/" searches,
+// "n" jumps to the next older match, "w" writes the log to a file
+fn message_log(tcod: &mut Tcod, game: &mut Game) {
+    use tcod::input::KeyCode::*;
+
+    // one entry per screen line, long messages wrap
+    let mut lines: Vec<(String, Color)> = vec![];
+    for message in game.messages.iter() {
+        for line in wrap_text(&message.line(), SCREEN_WIDTH as usize - 2) {
+            lines.push((line, message.color));
+        }
+    }
+
+    // last row is for the help/status line
+    let page = (SCREEN_HEIGHT - 1) as usize;
+    let max_top = lines.len().saturating_sub(page);
+    let mut top = max_top;
+    let mut query = String::new();
+    let mut current_match = None;
+    let mut status = String::from("Arrows/PgUp/PgDn: scroll  /: search  n: next  w: save log  Esc: close");
+
+    while !tcod.backend.window_closed() {
+        tcod.screen.set_default_background(BLACK);
+        tcod.screen.clear();
+        for (row, (line, color)) in lines.iter().skip(top).take(page).enumerate() {
+            let index = top + row;
+            let background = if Some(index) == current_match {
+                DARK_BLUE
+            } else if !query.is_empty() && line.to_lowercase().contains(&query.to_lowercase()) {
+                DARKEST_BLUE
+            } else {
+                BLACK
+            };
+            tcod.screen.set_default_background(background);
+            tcod.screen.set_default_foreground(*color);
+            tcod.screen.print_ex(1, row as i32, BackgroundFlag::Set, TextAlignment::Left, line);
+        }
+        tcod.screen.set_default_background(BLACK);
+        tcod.screen.set_default_foreground(LIGHT_GREY);
+        tcod.screen.print_ex(1, SCREEN_HEIGHT - 1, BackgroundFlag::Set, TextAlignment::Left, &status);
+        tcod.backend.present(&tcod.screen);
+
+        let key = tcod.backend.wait_for_keypress();
+        let mut searched = false;
+        match (key.code, key.text()) {
+            (Escape, _) => break,
+            (Up, _) => top = top.saturating_sub(1),
+            (Down, _) => top = cmp::min(top + 1, max_top),
+            (PageUp, _) => top = top.saturating_sub(page),
+            (PageDown, _) => top = cmp::min(top + page, max_top),
+            (Home, _) => top = 0,
+            (End, _) => top = max_top,
+            (Text, "/") => {
+                // type the search on the status line
+                query.clear();
+                loop {
+                    tcod.screen.rect(0, SCREEN_HEIGHT - 1, SCREEN_WIDTH, 1, true, BackgroundFlag::Set);
+                    tcod.screen.set_default_foreground(WHITE);
+                    tcod.screen.print_ex(
+                        1,
+                        SCREEN_HEIGHT - 1,
+                        BackgroundFlag::None,
+                        TextAlignment::Left,
+                        format!("Search: {}_", query),
+                    );
+                    tcod.backend.present(&tcod.screen);
+                    let key = tcod.backend.wait_for_keypress();
+                    if tcod.backend.window_closed() {
+                        return;
+                    }
+                    match key.code {
+                        Enter => break,
+                        Escape => {
+                            query.clear();
+                            break;
+                        }
+                        Backspace => {
+                            query.pop();
+                        }
+                        Spacebar => query.push(' '),
+                        Text => query.push_str(key.text()),
+                        _ => {}
+                    }
+                }
+                current_match = None;
+                if !query.is_empty() {
+                    current_match = find_line(&lines, &query, lines.len());
+                    searched = true;
+                }
+            }
+            (Text, "n") if !query.is_empty() => {
+                let before = current_match.unwrap_or(lines.len());
+                current_match = find_line(&lines, &query, before);
+                searched = true;
+            }
+            (Text, "w") => {
+                status = match game.messages.dump(MESSAGE_LOG_FILE) {
+                    Ok(()) => format!("Log written to {}.", MESSAGE_LOG_FILE),
+                    Err(e) => format!("Could not write {}: {}", MESSAGE_LOG_FILE, e),
+                };
+            }
+            _ => {}
+        }
+
+        // scroll a new match into view
+        if searched {
+            status = match current_match {
+                Some(index) => {
+                    if index < top || index >= top + page {
+                        top = cmp::min(index.saturating_sub(page / 2), max_top);
+                    }
+                    format!("Match for \"{}\" on line {}.", query, index + 1)
+                }
+                None => format!("No match for \"{}\".", query),
+            };
+        }
+    }
+}
+
+// shows each inventory item as an option
+fn inventory_menu(inventory: &[Object], header: &str, tcod: &mut Tcod) -> Option<usize> {
+    let options = if inventory.is_empty() {
+        vec!["Inventory is empty.".into()]
+    } else {
+        inventory
+            .iter()
+            .map(|item| {
+                // show where equipped items are worn
+                match item.equipment {
+                    Some(equipment) if equipment.equipped => {
+                        format!("{} (on {})", item.name, equipment.slot)
+                    }
+                    _ => item.name.clone(),
+                }
+            })
+            .collect()
+    };
+
+    let inventory_index = menu(header, &options, INVENTORY_WIDTH, tcod);
+
+    // return item if one was chosen
+    if !inventory.is_empty() {
+        inventory_index
+    } else {
+        None
+    }
 }
 
 // handle keyboard input
 fn handle_keys(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) -> PlayerAction {
-    use tcod::input::Key;
-    use tcod::input::KeyCode::*;
+    use Command::*;
     use PlayerAction::*;
 
-    // gets key
-    let key = tcod.root.wait_for_keypress(true);
+    // left click on an explored tile walks there
+    if tcod.mouse.lbutton_pressed {
+        let target = to_map(tcod.mouse.cx as i32, tcod.mouse.cy as i32, tcod.camera);
+        if let (Some(goal), true) = (target, objects[PLAYER].alive) {
+            let tile = &game.map[goal.0 as usize][goal.1 as usize];
+            if !tile.explored || tile.blocked {
+                game.messages.add("You don't know the way there.", RED);
+            } else if goal != objects[PLAYER].pos() {
+                travel(tcod, game, objects, &[goal]);
+            }
+        }
+        return DidntTakeTurn;
+    }
+
+    // command bound to the key polled this frame, if any
+    let command = match tcod.keymap.command_for(tcod.key) {
+        Some(command) => command,
+        None => return DidntTakeTurn,
+    };
 
     let player_alive = objects[PLAYER].alive;
 
     // specifies values we're interested in and what to do with them
-    match (key, key.text(), player_alive) {
-        // set alt+enter: toggle fullscreen
-        (Key { code: Enter, alt: true, .. }, _, _) => 
-        {  
-            let fullscreen = tcod.root.is_fullscreen();
-            tcod.root.set_fullscreen(!fullscreen);
+    match (command, player_alive) {
+        // toggle fullscreen
+        (Fullscreen, _) => {
+            let fullscreen = tcod.backend.is_fullscreen();
+            tcod.backend.set_fullscreen(!fullscreen);
             DidntTakeTurn
         },
-        
+
         // exit game
-        (Key { code: Escape, .. }, _, _) => return Exit, 
+        (Quit, _) => Exit,
 
         // movement keys
-        (Key { code: Up, .. }, _, true) => {
-            player_move_or_attack(0,-1, game, objects);
-            TookTurn
+        (MoveUp, true) => TookTurn(player_move_or_attack(0, -1, game, objects)),
+        (MoveDown, true) => TookTurn(player_move_or_attack(0, 1, game, objects)),
+        (MoveLeft, true) => TookTurn(player_move_or_attack(-1, 0, game, objects)),
+        (MoveRight, true) => TookTurn(player_move_or_attack(1, 0, game, objects)),
+        (MoveUpLeft, true) => TookTurn(player_move_or_attack(-1, -1, game, objects)),
+        (MoveUpRight, true) => TookTurn(player_move_or_attack(1, -1, game, objects)),
+        (MoveDownLeft, true) => TookTurn(player_move_or_attack(-1, 1, game, objects)),
+        (MoveDownRight, true) => TookTurn(player_move_or_attack(1, 1, game, objects)),
+
+        // stand still, takes as long as a step
+        (Wait, true) => TookTurn(MOVE_COST),
+
+        // wait over and over, runs the turns itself
+        (Rest, true) => {
+            rest(tcod, game, objects);
+            DidntTakeTurn
+        },
+
+        // walk to unexplored places until something comes up
+        (Explore, true) => {
+            auto_explore(tcod, game, objects);
+            DidntTakeTurn
+        },
+
+        // walk to the closest stairs already found
+        (TravelToStairs, true) => {
+            let stairs: Vec<_> = objects
+                .iter()
+                .filter(|object| object.is_stairs() && game.map[object.x as usize][object.y as usize].explored)
+                .map(|object| object.pos())
+                .collect();
+            if stairs.is_empty() {
+                game.messages.add("You haven't found any stairs yet.", RED);
+            } else if stairs.contains(&objects[PLAYER].pos()) {
+                game.messages.add("You are already on the stairs.", WHITE);
+            } else {
+                travel(tcod, game, objects, &stairs);
+            }
+            DidntTakeTurn
+        },
+
+        // pick up item under player
+        (PickUp, true) => {
+            let item_id = objects
+                .iter()
+                .position(|object| object.pos() == objects[PLAYER].pos() && object.item.is_some());
+            if let Some(item_id) = item_id {
+                pick_item_up(item_id, game, objects);
+            }
+            DidntTakeTurn
+        },
+
+        // show inventory, use chosen item
+        (Inventory, true) => {
+            let inventory_index = inventory_menu(
+                &game.inventory,
+                "Press the key next to an item to use it, or any other to cancel.\n",
+                tcod,
+            );
+            match inventory_index {
+                Some(inventory_index) if use_item(inventory_index, tcod, game, objects) => TookTurn(ITEM_COST),
+                _ => DidntTakeTurn,
+            }
+        },
+
+        // drop item
+        (DropItem, true) => {
+            let inventory_index = inventory_menu(
+                &game.inventory,
+                "Press the key next to an item to drop it, or any other to cancel.\n",
+                tcod,
+            );
+            if let Some(inventory_index) = inventory_index {
+                drop_item(inventory_index, game, objects);
+            }
+            DidntTakeTurn
         },
-        (Key { code: Down, .. }, _, true) => {
-            player_move_or_attack(0,1, game, objects);
-            TookTurn
+
+        // look back through the message log
+        (MessageLog, _) => {
+            message_log(tcod, game);
+            DidntTakeTurn
         },
-        (Key { code: Left, .. }, _, true) => {
-            player_move_or_attack(-1,0, game, objects);
-            TookTurn
+
+        // list what every key does
+        (Help, _) => {
+            help_screen(tcod);
+            DidntTakeTurn
         },
-        (Key { code: Right, .. }, _, true) => {
-            player_move_or_attack(1,0, game, objects);
-            TookTurn
+
+        // go down stairs if player is on them
+        (Descend, true) => {
+            let player_on_stairs = objects
+                .iter()
+                .any(|object| object.pos() == objects[PLAYER].pos() && object.is_stairs());
+            if player_on_stairs {
+                next_level(tcod, game, objects);
+            }
+            DidntTakeTurn
         },
 
         _ => DidntTakeTurn,
     }
 }
 
+// first monster the player can see, if any
+fn visible_monster(tcod: &Tcod, objects: &[Object]) -> Option<usize> {
+    objects.iter().position(|object| {
+        object.fighter.is_some() && object.ai.is_some() && tcod.fov.is_in_fov(object.x, object.y)
+    })
+}
 
+// waits turn after turn until fully healed, which takes regeneration,
+// or a monster shows up
+fn rest(tcod: &Tcod, game: &mut Game, objects: &mut [Object]) {
+    if let Some(id) = visible_monster(tcod, objects) {
+        game.messages.add(format!("You can't rest with {} around.", objects[id].name), RED);
+        return;
+    }
+    let max_hp = objects[PLAYER].max_hp(game);
+    if objects[PLAYER].fighter.map_or(0, |f| f.hp) >= max_hp {
+        game.messages.add("You are already at full health.", WHITE);
+        return;
+    }
 
+    for _ in 0..REST_MAX_TURNS {
+        let hp_before = objects[PLAYER].fighter.map_or(0, |f| f.hp);
+        objects[PLAYER].energy -= MOVE_COST;
+        run_until_player_turn(tcod, game, objects);
+
+        let hp = objects[PLAYER].fighter.map_or(0, |f| f.hp);
+        if !objects[PLAYER].alive {
+            return;
+        } else if let Some(id) = visible_monster(tcod, objects) {
+            game.messages.add(format!("You stop resting, {} comes into view.", objects[id].name), RED);
+            return;
+        } else if hp < hp_before {
+            game.messages.add("You stop resting, something hurts.", RED);
+            return;
+        } else if hp >= objects[PLAYER].max_hp(game) {
+            game.messages.add("You feel rested.", LIGHT_VIOLET);
+            return;
+        }
+    }
+    game.messages.add("You get up, too restless to sleep.", WHITE);
+}
 
-/// MAIN FUNCTION ///
-///////////////////////////////////////////////
-///////////////////////////////////////////////
-/////////////////////
+// first step towards the closest unexplored tile the player can walk to,
+// None once there's nothing left or the way is blocked
+fn explore_step(explore_map: &mut DijkstraMap, map: &Map, objects: &[Object]) -> Option<(i32, i32)> {
+    let mut goals = vec![];
+    for (x, column) in map.iter().enumerate() {
+        for (y, tile) in column.iter().enumerate() {
+            if !tile.explored && !tile.blocked {
+                goals.push(((x as i32, y as i32), 0));
+            }
+        }
+    }
+    explore_map.update(map, &goals, |tile| !tile.blocked);
+    explore_map.best_step(objects[PLAYER].pos(), map, objects)
+}
 
+// items in fov, to tell new ones apart from ones already seen
+fn visible_items(tcod: &Tcod, objects: &[Object]) -> Vec<(i32, i32)> {
+    objects
+        .iter()
+        .filter(|object| object.item.is_some() && tcod.fov.is_in_fov(object.x, object.y))
+        .map(|object| object.pos())
+        .collect()
+}
 
+// one step of exploring or travelling: moves, lets monsters act and shows
+// the result. returns why to stop if something came up
+fn auto_step(tcod: &mut Tcod, game: &mut Game, objects: &mut [Object], step: (i32, i32), activity: &str) -> Option<String> {
+    // any key interrupts, and is used up doing so
+    if tcod.backend.key_pending() {
+        tcod.backend.check_for_event();
+        return Some(format!("You stop {}.", activity));
+    }
 
+    let hp_before = objects[PLAYER].fighter.map_or(0, |f| f.hp);
+    let position_before = objects[PLAYER].pos();
+    let cost = player_move_or_attack(step.0, step.1, game, objects);
+    objects[PLAYER].energy -= cost;
+    run_until_player_turn(tcod, game, objects);
+
+    // show the step, also updates fov and explored tiles
+    tcod.con.clear();
+    render_all(tcod, game, objects, true);
+    tcod.backend.present(&tcod.screen);
+
+    if !objects[PLAYER].alive || tcod.backend.window_closed() {
+        Some(String::new())
+    } else if let Some(id) = visible_monster(tcod, objects) {
+        Some(format!("You stop {}, {} comes into view.", activity, objects[id].name))
+    } else if objects[PLAYER].fighter.map_or(0, |f| f.hp) < hp_before {
+        Some(format!("You stop {}, something hurts.", activity))
+    } else if objects[PLAYER].pos() == position_before {
+        Some(format!("You stop {}, something is in the way.", activity))
+    } else {
+        None
+    }
+}
 
-fn main() {
-    let root = Root::initializer()
-        .font("arial10x10.png", FontLayout::Tcod)
-        .font_type(FontType::Greyscale)
-        .size(SCREEN_WIDTH, SCREEN_HEIGHT)
-        .title("yet another roguelike v 0.01")
-        .init();
+// steps toward unexplored tiles, a turn at a time, until a monster or a
+// new item comes into view, the player gets hurt, a key is pressed or
+// the level is done
+fn auto_explore(tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) {
+    if let Some(id) = visible_monster(tcod, objects) {
+        game.messages.add(format!("You can't explore with {} around.", objects[id].name), RED);
+        return;
+    }
+    let mut seen_items = visible_items(tcod, objects);
+    let mut explore_map = DijkstraMap::default();
+
+    let reason = loop {
+        let step = match explore_step(&mut explore_map, &game.map, objects) {
+            Some(step) => step,
+            // still somewhere to go, something stands in the only way
+            None if explore_map.distance(objects[PLAYER].x, objects[PLAYER].y).is_some() => {
+                break "You stop exploring, something is in the way.".to_string()
+            }
+            None => break "Nothing left to explore here.".to_string(),
+        };
+        if let Some(reason) = auto_step(tcod, game, objects, step, "exploring") {
+            break reason;
+        }
+        let items = visible_items(tcod, objects);
+        if let Some(&pos) = items.iter().find(|pos| !seen_items.contains(pos)) {
+            let name = objects
+                .iter()
+                .find(|object| object.item.is_some() && object.pos() == pos)
+                .map_or(String::new(), |object| object.name.clone());
+            break format!("You stop exploring, you spot a {}.", name);
+        }
+        seen_items = items;
+    };
+    if !reason.is_empty() {
+        game.messages.add(reason, LIGHT_CYAN);
+    }
+}
 
-    let mut tcod = Tcod { 
-        root, 
-        con: Offscreen::new(MAP_WIDTH, MAP_HEIGHT),
-        panel: Offscreen::new(SCREEN_WIDTH, PANEL_HEIGHT),
-        fov: FovMap::new(MAP_WIDTH, MAP_HEIGHT),
+// walks the shortest known way to the closest of goals, a turn at a
+// time, stopping as soon as a monster shows up
+fn travel(tcod: &mut Tcod, game: &mut Game, objects: &mut [Object], goals: &[(i32, i32)]) {
+    if let Some(id) = visible_monster(tcod, objects) {
+        game.messages.add(format!("You can't travel with {} around.", objects[id].name), RED);
+        return;
+    }
+    // only over explored tiles, worked out once so tiles seen on the way
+    // don't change the route
+    let goals: Vec<_> = goals.iter().map(|&goal| (goal, 0)).collect();
+    let mut travel_map = DijkstraMap::default();
+    travel_map.update(&game.map, &goals, |tile| tile.explored && !tile.blocked);
+    let (x, y) = objects[PLAYER].pos();
+    if travel_map.distance(x, y).is_none() {
+        game.messages.add("You don't know the way there.", RED);
+        return;
+    }
+
+    let reason = loop {
+        let (x, y) = objects[PLAYER].pos();
+        if travel_map.distance(x, y) == Some(0) {
+            return;
+        }
+        let step = match travel_map.best_step((x, y), &game.map, objects) {
+            Some(step) => step,
+            None => break "You stop travelling, something is in the way.".to_string(),
+        };
+        if let Some(reason) = auto_step(tcod, game, objects, step, "travelling") {
+            break reason;
+        }
     };
-    
+    if !reason.is_empty() {
+        game.messages.add(reason, LIGHT_CYAN);
+    }
+}
+
+// lists every command w/ the keys currently bound to it
+fn help_screen(tcod: &mut Tcod) {
+    let mut text = String::from("Keys\n\n");
+    for &command in Command::ALL {
+        let keys: Vec<_> = tcod
+            .keymap
+            .keys_for(command)
+            .iter()
+            .map(|spec| spec.name.as_str())
+            .collect();
+        let keys = if keys.is_empty() { "-".to_string() } else { keys.join(", ") };
+        text.push_str(&format!("{:<20}{}\n", command.description(), keys));
+    }
+    text.push_str("\nPress any key to continue.");
+    msgbox(&text, HELP_WIDTH, tcod);
+}
+
+
+
+
+// create player and first level from given seed
+fn new_game(
+    seed: u64,
+    generator: MapGenerator,
+    monsters: &[MonsterTemplate],
+    history: usize,
+) -> (Game, Vec<Object>) {
     // create object representing the player
     let mut player = Object::new(0, 0, '@', "player", WHITE, true);
     player.alive = true;
     player.fighter = Some(Fighter {
-        max_hp: 30,
+        base_max_hp: 30,
         hp: 30,
-        defense: 2,
-        power: 5,
+        base_defense: 2,
+        base_power: 5,
+        xp: 0,
+        level: 1,
         on_death: DeathCallback::Player,
+        on_hit: None,
     });
 
     // create npc
@@ -804,70 +3511,1320 @@ fn main() {
     // list of objects
     let mut objects = vec![player];
 
-    // generate map
     let mut game = Game {
-        map: make_map(&mut objects),
-        messages: Messages::new(),
+        map: vec![],
+        messages: Messages::new(history),
+        inventory: vec![],
+        dungeon_level: 1,
+        generator,
+        seed,
+        rng: GameRng::new(seed),
+        monsters: monsters.to_vec(),
+        chase_map: DijkstraMap::default(),
+        flee_map: DijkstraMap::default(),
     };
 
+    // generate map
+    game.map = make_map(
+        &mut objects,
+        game.dungeon_level,
+        game.generator,
+        &game.monsters,
+        &mut game.rng,
+    );
+
     game.messages.add(
         "Welcome student! Prepare to perish in the Neoliberal Corporatocracy.",
         BLUE,
     );
 
-    // populate fov map acc generated map
+    (game, objects)
+}
+
+// xp needed to advance from given level
+fn level_up_xp(level: i32) -> i32 {
+    LEVEL_UP_BASE + level * LEVEL_UP_FACTOR
+}
+
+// levels up player once enough xp is collected, letting them pick a stat
+fn level_up(tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) {
+    let player = &mut objects[PLAYER];
+    let fighter = match player.fighter.as_mut() {
+        Some(fighter) => fighter,
+        None => return,
+    };
+    let needed_xp = level_up_xp(fighter.level);
+    if fighter.xp < needed_xp {
+        return;
+    }
+
+    // keep asking until a stat is picked
+    let mut choice = None;
+    while choice.is_none() && !tcod.backend.window_closed() {
+        choice = menu(
+            "Level up! Choose a stat to raise:\n",
+            &[
+                format!("Constitution (+20 HP, from {})", fighter.base_max_hp),
+                format!("Strength (+1 attack, from {})", fighter.base_power),
+                format!("Agility (+1 defense, from {})", fighter.base_defense),
+            ],
+            LEVEL_SCREEN_WIDTH,
+            tcod,
+        );
+    }
+    let choice = match choice {
+        Some(choice) => choice,
+        // window closed mid-choice, ask again next time
+        None => return,
+    };
+
+    fighter.level += 1;
+    fighter.xp -= needed_xp;
+    match choice {
+        0 => {
+            fighter.base_max_hp += 20;
+            fighter.hp += 20;
+        }
+        1 => fighter.base_power += 1,
+        2 => fighter.base_defense += 1,
+        _ => unreachable!(),
+    }
+    game.messages.add(
+        format!(
+            "Your organizing skills grow stronger! You reached level {}!",
+            fighter.level
+        ),
+        YELLOW,
+    );
+}
+
+// go down stairs, generating a fresh level around the player
+fn next_level(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
+    game.messages.add(
+        "You take a moment to rest, and recover your strength.",
+        VIOLET,
+    );
+    let heal_hp = objects[PLAYER].max_hp(game) / 2;
+    objects[PLAYER].heal(heal_hp, game);
+
+    game.messages.add(
+        "After a rare moment of peace, you descend deeper into the heart of the system...",
+        RED,
+    );
+    game.dungeon_level += 1;
+    game.map = make_map(
+        objects,
+        game.dungeon_level,
+        game.generator,
+        &game.monsters,
+        &mut game.rng,
+    );
+    initialise_fov(tcod, &game.map);
+}
+
+// populate fov map acc generated map
+fn initialise_fov(tcod: &mut Tcod, map: &Map) {
     for y in 0..MAP_HEIGHT {
         for x in 0..MAP_WIDTH {
             tcod.fov.set(
                 x,
                 y,
-                !game.map[x as usize][y as usize].block_sight,
-                !game.map[x as usize][y as usize].blocked,
+                !map[x as usize][y as usize].block_sight,
+                !map[x as usize][y as usize].blocked,
             );
         }
     }
+}
+
+// builds a key press the same way libtcod reports it,
+// a mouse move for `mouse:<x>,<y>` or a left click for `click:<x>,<y>`
+// (screen cells)
+fn scripted_event(name: &str) -> Option<Event> {
+    use tcod_sys::TCOD_keycode_t::*;
 
+    let (coords, click) = match (name.strip_prefix("mouse:"), name.strip_prefix("click:")) {
+        (Some(coords), _) => (Some(coords), false),
+        (_, Some(coords)) => (Some(coords), true),
+        _ => (None, false),
+    };
+    if let Some(coords) = coords {
+        let mut coords = coords.split(',').map(|c| c.parse::<isize>());
+        let (cx, cy) = match (coords.next(), coords.next(), coords.next()) {
+            (Some(Ok(cx)), Some(Ok(cy)), None) => (cx, cy),
+            _ => return None,
+        };
+        return Some(Event::Mouse(Mouse {
+            cx,
+            cy,
+            lbutton_pressed: click,
+            ..Default::default()
+        }));
+    }
+
+    let vk = match name {
+        "up" => TCODK_UP,
+        "down" => TCODK_DOWN,
+        "left" => TCODK_LEFT,
+        "right" => TCODK_RIGHT,
+        "enter" => TCODK_ENTER,
+        "esc" => TCODK_ESCAPE,
+        "pageup" => TCODK_PAGEUP,
+        "pagedown" => TCODK_PAGEDOWN,
+        "backspace" => TCODK_BACKSPACE,
+        _ if name.chars().count() == 1 => TCODK_TEXT,
+        _ => return None,
+    };
+
+    // text keys carry the typed character
+    let mut text = [0; 32];
+    let mut c = 0;
+    if vk == TCODK_TEXT {
+        for (dst, src) in text.iter_mut().zip(name.bytes()) {
+            *dst = src as std::os::raw::c_char;
+        }
+        c = text[0];
+    }
+
+    Some(Event::Key(Key::from(tcod_sys::TCOD_key_t {
+        vk,
+        c,
+        text,
+        pressed: 1,
+        lalt: 0,
+        lctrl: 0,
+        lmeta: 0,
+        ralt: 0,
+        rctrl: 0,
+        rmeta: 0,
+        shift: 0,
+    })))
+}
+
+// command line options
+struct Args {
+    // `--seed <n>`
+    seed: Option<u64>,
+    // `--script "<events>"`, runs headless w/ space separated key names
+    script: Option<Vec<Event>>,
+    // `--generator rooms|bsp|caves`
+    generator: MapGenerator,
+    // `--history <n>`, messages kept in the log
+    history: usize,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut parsed = Args {
+        seed: None,
+        script: None,
+        generator: MapGenerator::Rooms,
+        history: MSG_HISTORY,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => {
+                let value = args.next().ok_or("--seed needs a value")?;
+                let seed = value
+                    .parse()
+                    .map_err(|_| format!("invalid seed: {}", value))?;
+                parsed.seed = Some(seed);
+            }
+            "--script" => {
+                let value = args.next().ok_or("--script needs a value")?;
+                let events = value
+                    .split_whitespace()
+                    .map(|name| scripted_event(name).ok_or(format!("unknown key: {}", name)))
+                    .collect::<Result<_, _>>()?;
+                parsed.script = Some(events);
+            }
+            "--generator" => {
+                let value = args.next().ok_or("--generator needs a value")?;
+                parsed.generator = match value.as_str() {
+                    "rooms" => MapGenerator::Rooms,
+                    "bsp" => MapGenerator::Bsp,
+                    "caves" => MapGenerator::Caves,
+                    _ => return Err(format!("unknown generator: {}", value)),
+                };
+            }
+            "--history" => {
+                let value = args.next().ok_or("--history needs a value")?;
+                parsed.history = match value.parse() {
+                    Ok(history) if history > 0 => history,
+                    _ => return Err(format!("invalid history size: {}", value)),
+                };
+            }
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    Ok(parsed)
+}
+
+// title screen, loops until player quits or window closes
+fn main_menu(
+    tcod: &mut Tcod,
+    seed: Option<u64>,
+    generator: MapGenerator,
+    monsters: &[MonsterTemplate],
+    history: usize,
+) {
+    while !tcod.backend.window_closed() {
+        // draw banner and credits on a blank screen
+        tcod.screen.set_default_background(BLACK);
+        tcod.screen.clear();
+        tcod.screen.set_default_foreground(LIGHT_YELLOW);
+        let banner_y = SCREEN_HEIGHT / 2 - 12;
+        for (i, line) in TITLE_BANNER.iter().enumerate() {
+            tcod.screen.print_ex(
+                SCREEN_WIDTH / 2,
+                banner_y + i as i32,
+                BackgroundFlag::None,
+                TextAlignment::Center,
+                *line,
+            );
+        }
+        tcod.screen.set_default_foreground(LIGHT_GREY);
+        tcod.screen.print_ex(
+            SCREEN_WIDTH / 2,
+            banner_y + TITLE_BANNER.len() as i32 + 2,
+            BackgroundFlag::None,
+            TextAlignment::Center,
+            "Prepare to perish in the Neoliberal Corporatocracy",
+        );
+        tcod.screen.print_ex(
+            SCREEN_WIDTH / 2,
+            SCREEN_HEIGHT - 2,
+            BackgroundFlag::None,
+            TextAlignment::Center,
+            "By volfym",
+        );
+
+        // show options and wait for player's choice
+        let choices = &["New game", "Continue", "Quit"];
+        let choice = menu("", choices, MAIN_MENU_WIDTH, tcod);
+
+        match choice {
+            Some(0) => {
+                // new game, w/ seed from command line if given
+                let (mut game, mut objects) =
+                    new_game(seed.unwrap_or_else(rand::random), generator, monsters, history);
+                run_game(tcod, &mut game, &mut objects);
+            }
+            Some(1) => {
+                // load last game
+                if !Path::new(SAVE_FILE).exists() {
+                    msgbox("\nNo saved game to load.\n", MSGBOX_WIDTH, tcod);
+                    continue;
+                }
+                match load_game(monsters) {
+                    Ok((mut game, mut objects)) => {
+                        game.messages.set_capacity(history);
+                        game.messages.add("Welcome back, student.", BLUE);
+                        run_game(tcod, &mut game, &mut objects);
+                    }
+                    Err(e) => {
+                        let text = format!("\nCould not load saved game: {}\n", e);
+                        msgbox(&text, MSGBOX_WIDTH, tcod);
+                    }
+                }
+            }
+            Some(2) => {
+                // quit
+                break;
+            }
+            _ => {}
+        }
+    }
+}
+
+// plays until player leaves, then saves or clears finished run
+fn run_game(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
+    initialise_fov(tcod, &game.map);
+    play_game(tcod, game, objects);
+
+    let result = if objects[PLAYER].alive {
+        save_game(game, objects)
+    } else if Path::new(SAVE_FILE).exists() {
+        // run is over, nothing to continue
+        std::fs::remove_file(SAVE_FILE).map_err(|e| e.into())
+    } else {
+        Ok(())
+    };
+    if let Err(e) = result {
+        let text = format!("\nCould not update saved game: {}\n", e);
+        msgbox(&text, MSGBOX_WIDTH, tcod);
+    }
+}
+
+// main game loop, runs until player quits or window closes
+fn play_game(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
     // force FOV to recompute first time thru game loop
     let mut previous_player_position = (-1, -1);
 
-    while !tcod.root.window_closed() {
+    while !tcod.backend.window_closed() {
+        // a click only counts on the frame it came in
+        tcod.mouse.lbutton_pressed = false;
+
+        // poll for key press or mouse move, one per frame
+        match tcod.backend.check_for_event() {
+            Some(Event::Mouse(mouse)) => {
+                tcod.mouse = mouse;
+                tcod.key = Default::default();
+            }
+            Some(Event::Key(key)) => tcod.key = key,
+            None => tcod.key = Default::default(),
+        }
+
         // clear screen of previous frame
         tcod.con.clear();
 
         // renders screen
-        /* render_all(&mut tcod, &game, &objects);*/
         let fov_recompute = previous_player_position != (objects[PLAYER].x, objects[PLAYER].y);
-        render_all(&mut tcod, &mut game, &objects, fov_recompute);
+        render_all(tcod, game, objects, fov_recompute);
 
         // draws everything at once
-        tcod.root.flush();
-
-        // waits for input
-        tcod.root.wait_for_keypress(true);
-
-        for object in &objects {
-               object.draw(&mut tcod.con);
+        tcod.backend.present(&tcod.screen);
+
+        // run is over, back to main menu
+        if !objects[PLAYER].alive {
+            msgbox(
+                "Game over.\n\nPress any key to return to the menu.",
+                MSGBOX_WIDTH,
+                tcod,
+            );
+            break;
         }
 
-        
-        
-        // handle keys n exit game
+        // level up if needed
+        level_up(tcod, game, objects);
 
+        // handle keys n exit game
         previous_player_position = objects[PLAYER].pos();
-        let player_action = handle_keys(&mut tcod, &mut game, &mut objects);
+        let player_action = handle_keys(tcod, game, objects);
+        if player_action == PlayerAction::Exit {
+            break;
+        }
 
         // let monsters take turn
-        if objects[PLAYER].alive && player_action != PlayerAction::DidntTakeTurn {
+        if let PlayerAction::TookTurn(cost) = player_action {
+            objects[PLAYER].energy -= cost;
+            run_until_player_turn(tcod, game, objects);
+        }
+    }
+}
+
+// ticks the clock until the player has the energy to act again. every
+// fighter gains its speed in energy each tick, monsters act while they
+// have energy to spare, so fast ones can go several times in a turn
+fn run_until_player_turn(tcod: &Tcod, game: &mut Game, objects: &mut [Object]) {
+    loop {
+        while objects[PLAYER].alive && objects[PLAYER].energy < 0 {
+            for object in objects.iter_mut().filter(|o| o.fighter.is_some()) {
+                object.energy += object.current_speed();
+            }
             for id in 0..objects.len() {
-                if objects[id].ai.is_some() {
-                    ai_take_turn(id, &tcod, &mut game, &mut objects);
+                while objects[PLAYER].alive && objects[id].ai.is_some() && objects[id].energy >= 0 {
+                    let cost = if tick_effects(id, game, objects) {
+                        ai_take_turn(id, tcod, game, objects)
+                    } else {
+                        MOVE_COST
+                    };
+                    objects[id].energy -= cost;
                 }
             }
         }
 
-        if player_action == PlayerAction::Exit {
+        // player's turn is up, unless stunned
+        if !objects[PLAYER].alive || tick_effects(PLAYER, game, objects) {
             break;
         }
+        objects[PLAYER].energy -= MOVE_COST;
+    }
+}
+
+// runs one turn of every effect on an object and counts them down.
+// returns false if the object can't act this turn
+fn tick_effects(id: usize, game: &mut Game, objects: &mut [Object]) -> bool {
+    let stunned = objects[id].has_effect(EffectKind::Stun);
+    for effect in objects[id].effects.clone() {
+        match effect.kind {
+            EffectKind::Poison => {
+                if let Some(xp) = objects[id].take_damage(effect.strength, game) {
+                    // poison kills count as the player's
+                    if id != PLAYER {
+                        objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+                    }
+                }
+            }
+            EffectKind::Regeneration => objects[id].heal(effect.strength, game),
+            EffectKind::Stun | EffectKind::Haste => {}
+        }
+        if !objects[id].alive {
+            return false;
+        }
+    }
+
+    // count down, announce the ones that ran out
+    let mut expired = vec![];
+    objects[id].effects.retain_mut(|effect| {
+        effect.turns -= 1;
+        if effect.turns <= 0 {
+            expired.push(effect.kind);
+        }
+        effect.turns > 0
+    });
+    for kind in expired {
+        game.messages.add(format!("{} {}", objects[id].name, kind.end_message()), kind.color());
+    }
+    !stunned
+}
+
+// parses a keys file into bindings, on top of the ones already in keymap
+fn parse_keymap(text: &str, keymap: &mut Keymap) -> Result<(), String> {
+    let file: HashMap<String, Vec<String>> = toml::from_str(text).map_err(|e| e.to_string())?;
+    for (command, names) in file {
+        let command = Command::ALL
+            .iter()
+            .cloned()
+            .find(|c| c.name() == command)
+            .ok_or(format!("unknown command: {}", command))?;
+        let keys = names
+            .iter()
+            .map(|name| KeySpec::parse(name).ok_or(format!("unknown key: {}", name)))
+            .collect::<Result<_, _>>()?;
+        keymap.bindings.insert(command, keys);
+    }
+
+    // one key can't do two things
+    for (i, &command) in Command::ALL.iter().enumerate() {
+        for &other in &Command::ALL[i + 1..] {
+            for spec in keymap.keys_for(command) {
+                if keymap.keys_for(other).contains(spec) {
+                    return Err(format!(
+                        "{} is bound to both {} and {}",
+                        spec.name,
+                        command.name(),
+                        other.name()
+                    ));
+                }
+            }
+        }
     }
+    Ok(())
 }
 
+// default bindings, w/ whatever the keys file changes. a missing file is fine
+fn load_keymap(path: &str) -> Result<Keymap, String> {
+    let mut keymap = Keymap::default();
+    parse_keymap(DEFAULT_KEYS, &mut keymap).map_err(|e| format!("default keys: {}", e))?;
+    if Path::new(path).exists() {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        parse_keymap(&text, &mut keymap).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    Ok(keymap)
+}
+
+// consoles and input state around a backend, same for window or headless
+fn new_tcod(backend: &mut dyn Backend, keymap: Keymap) -> Tcod<'_> {
+    Tcod {
+        backend,
+        screen: Offscreen::new(SCREEN_WIDTH, SCREEN_HEIGHT),
+        con: Offscreen::new(CAMERA_WIDTH, CAMERA_HEIGHT),
+        panel: Offscreen::new(SCREEN_WIDTH, PANEL_HEIGHT),
+        fov: FovMap::new(MAP_WIDTH, MAP_HEIGHT),
+        key: Default::default(),
+        mouse: Default::default(),
+        keymap,
+        camera: (0, 0),
+    }
+}
+
+// plays a new game w/o a window until the events run out. the backend
+// comes back holding the last frame
+fn run_script(
+    events: Vec<Event>,
+    seed: u64,
+    generator: MapGenerator,
+    monsters: &[MonsterTemplate],
+    keymap: Keymap,
+    history: usize,
+) -> (HeadlessBackend, Game, Vec<Object>) {
+    let mut backend = HeadlessBackend::new(events);
+    let (mut game, mut objects) = new_game(seed, generator, monsters, history);
+    {
+        let mut tcod = new_tcod(&mut backend, keymap);
+        initialise_fov(&mut tcod, &game.map);
+        play_game(&mut tcod, &mut game, &mut objects);
+    }
+    (backend, game, objects)
+}
+
+// reads and checks monster templates
+fn load_monsters(path: &str) -> Result<Vec<MonsterTemplate>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let file: MonsterFile = toml::from_str(&text).map_err(|e| format!("{}: {}", path, e))?;
+
+    if file.monster.is_empty() {
+        return Err(format!("{}: no monsters defined", path));
+    }
+    for (i, template) in file.monster.iter().enumerate() {
+        let problem = if template.name.is_empty() {
+            Some("name must not be empty")
+        } else if file.monster[..i].iter().any(|other| other.name == template.name) {
+            Some("name is already used by another monster")
+        } else if template.hp <= 0 {
+            Some("hp must be positive")
+        } else if template.defense < 0 || template.power < 0 || template.xp < 0 {
+            Some("defense, power and xp must not be negative")
+        } else if template.spawn_weight.iter().all(|t| t.value == 0) {
+            Some("spawn_weight needs a positive weight")
+        } else if template.spawn_weight[0].level == 0 {
+            Some("spawn_weight levels start at 1")
+        } else if template.spawn_weight.windows(2).any(|w| w[0].level >= w[1].level) {
+            Some("spawn_weight levels must go up")
+        } else if template.speed <= 0 {
+            Some("speed must be positive")
+        } else if template.on_hit.is_some_and(|e| e.turns <= 0) {
+            Some("on_hit turns must be positive")
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            return Err(format!(
+                "{}: monster #{} ({}): {}",
+                path,
+                i + 1,
+                template.name,
+                problem
+            ));
+        }
+    }
+    Ok(file.monster)
+}
+
+// write game state to save file, tagged w/ format version
+fn save_game(game: &Game, objects: &[Object]) -> Result<(), Box<dyn Error>> {
+    let save_data = serde_json::to_string(&(SAVE_VERSION, game, objects))?;
+    let mut file = File::create(SAVE_FILE)?;
+    file.write_all(save_data.as_bytes())?;
+    Ok(())
+}
+
+// read game state back from save file
+fn load_game(monsters: &[MonsterTemplate]) -> Result<(Game, Vec<Object>), Box<dyn Error>> {
+    let mut json_save_state = String::new();
+    let mut file = File::open(SAVE_FILE)?;
+    file.read_to_string(&mut json_save_state)?;
+
+    let save_data: serde_json::Value = serde_json::from_str(&json_save_state)
+        .map_err(|e| format!("save file is corrupt: {}", e))?;
+
+    // check version before reading the rest, layout may differ
+    match save_data.get(0).and_then(|version| version.as_u64()) {
+        Some(SAVE_VERSION) => {}
+        Some(version) => {
+            return Err(format!(
+                "save file version {} is not supported (expected {})",
+                version, SAVE_VERSION
+            )
+            .into())
+        }
+        None => return Err("save file is corrupt: missing version".into()),
+    }
+
+    let (_, mut game, objects): (u64, Game, Vec<Object>) = serde_json::from_value(save_data)
+        .map_err(|e| format!("save file is corrupt: {}", e))?;
+    game.monsters = monsters.to_vec();
+    Ok((game, objects))
+}
+
+
+
+
+/// MAIN FUNCTION ///
+///////////////////////////////////////////////
+///////////////////////////////////////////////
+/////////////////////
+
+
+
+
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let monsters = match load_monsters(MONSTERS_FILE) {
+        Ok(monsters) => monsters,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let keymap = match load_keymap(KEYS_FILE) {
+        Ok(keymap) => keymap,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // scripted run w/o a window, prints the last frame
+    if let Some(events) = args.script {
+        let seed = args.seed.unwrap_or_else(rand::random);
+        let (backend, _, _) = run_script(events, seed, args.generator, &monsters, keymap, args.history);
+        print!("{}", backend.to_text());
+        return;
+    }
+
+    let root = Root::initializer()
+        .font("arial10x10.png", FontLayout::Tcod)
+        .font_type(FontType::Greyscale)
+        .size(SCREEN_WIDTH, SCREEN_HEIGHT)
+        .title("yet another roguelike v 0.01")
+        .init();
+
+    tcod::system::set_fps(LIMIT_FPS);
+
+    let mut backend = TcodBackend { root, pending: None };
+    let mut tcod = new_tcod(&mut backend, keymap);
+
+    main_menu(&mut tcod, args.seed, args.generator, &monsters, args.history);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: u64 = 42;
+
+    fn default_keymap() -> Keymap {
+        let mut keymap = Keymap::default();
+        parse_keymap(DEFAULT_KEYS, &mut keymap).unwrap();
+        keymap
+    }
+
+    // something in the way, no stats
+    fn monster(x: i32, y: i32) -> Object {
+        Object::new(x, y, 'm', "monster", WHITE, true)
+    }
+
+    // open floor with walls on the given tiles
+    fn small_map(width: usize, height: usize, walls: &[(usize, usize)]) -> Map {
+        let mut map = vec![vec![Tile::empty(); height]; width];
+        for &(x, y) in walls {
+            map[x][y] = Tile::wall();
+        }
+        map
+    }
+
+    fn open(tile: &Tile) -> bool {
+        !tile.blocked
+    }
+
+    // a shipped monster, by name
+    fn spawn(name: &str, x: i32, y: i32) -> Object {
+        let monsters = load_monsters(MONSTERS_FILE).unwrap();
+        monsters.iter().find(|template| template.name == name).unwrap().spawn(x, y)
+    }
+
+    // full size level, wall everywhere but the given rooms
+    fn dug_map(rooms: &[Rect]) -> Map {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        for &room in rooms {
+            create_room(room, &mut map);
+        }
+        map
+    }
+
+    // fresh game moved onto map, nothing but the player on it
+    fn game_on(map: Map, start: (i32, i32)) -> (Game, Vec<Object>) {
+        let monsters = load_monsters(MONSTERS_FILE).unwrap();
+        let (mut game, mut objects) = new_game(SEED, MapGenerator::Rooms, &monsters, MSG_HISTORY);
+        game.map = map;
+        objects.truncate(1);
+        objects[PLAYER].set_pos(start.0, start.1);
+        (game, objects)
+    }
+
+    // fov from where the player stands, same as render_all works it out
+    fn look(tcod: &mut Tcod, game: &Game, objects: &[Object]) {
+        initialise_fov(tcod, &game.map);
+        let (x, y) = objects[PLAYER].pos();
+        tcod.fov.compute_fov(x, y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+    }
+
+    #[test]
+    fn scripted_move_is_drawn() {
+        let monsters = load_monsters(MONSTERS_FILE).unwrap();
+
+        // same seed gives the same level, find a free tile next to the start
+        let (game, objects) = new_game(SEED, MapGenerator::Rooms, &monsters, MSG_HISTORY);
+        let (x, y) = objects[PLAYER].pos();
+        let (key, dx, dy) = [("right", 1, 0), ("left", -1, 0), ("down", 0, 1), ("up", 0, -1)]
+            .iter()
+            .cloned()
+            .find(|&(_, dx, dy)| !is_blocked(x + dx, y + dy, &game.map, &objects))
+            .expect("start is walled in");
+
+        let events = vec![scripted_event(key).unwrap()];
+        let (backend, _, objects) =
+            run_script(events, SEED, MapGenerator::Rooms, &monsters, default_keymap(), MSG_HISTORY);
+        let to = (x + dx, y + dy);
+        assert_eq!(objects[PLAYER].pos(), to);
+
+        // player drawn where they went, both tiles lit floor
+        let camera = camera_origin(to);
+        let (screen_x, screen_y) = to_camera(to.0, to.1, camera).unwrap();
+        let cell = backend.cell(screen_x, screen_y);
+        assert_eq!(cell.char, '@');
+        assert_eq!(cell.foreground, WHITE);
+        assert_eq!(cell.background, COLOR_LIGHT_GROUND);
+        let (screen_x, screen_y) = to_camera(x, y, camera).unwrap();
+        assert_eq!(backend.cell(screen_x, screen_y).background, COLOR_LIGHT_GROUND);
+
+        assert!(backend.to_text().contains("Dungeon level: 1"));
+    }
+
+    #[test]
+    fn trump_share_grows_with_depth() {
+        let monsters = load_monsters(MONSTERS_FILE).unwrap();
+        let weight = |name: &str, level| {
+            let template = monsters.iter().find(|t| t.name == name).unwrap();
+            from_dungeon_level(&template.spawn_weight, level)
+        };
+        // percent of trumps among bezos and trump, same as before templates
+        for &(level, percent) in &[(1, 20), (3, 30), (5, 45), (7, 60)] {
+            let trump = weight("trump", level);
+            assert_eq!(trump * 100 / (trump + weight("bezos", level)), percent);
+        }
+    }
+
+    #[test]
+    fn validate_map_finds_cut_off_room_and_buried_monster() {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        create_room(Rect::new(1, 1, 6, 6), &mut map);
+        create_room(Rect::new(20, 20, 6, 6), &mut map);
+
+        let player = Object::new(3, 3, '@', "player", WHITE, true);
+        let objects = vec![player, monster(4, 4), monster(10, 10)];
+
+        let report = validate_map(&map, &objects);
+        assert!(!report.is_ok());
+        assert_eq!(report.unreachable.len(), 1);
+        assert!(report.unreachable[0].contains(&(23, 23)));
+        assert_eq!(report.blocked_spawns, vec![2]);
+
+        // a tunnel between them fixes the rooms but not the monster
+        create_h_tunnel(3, 23, 3, &mut map);
+        create_v_tunnel(3, 23, 23, &mut map);
+        let report = validate_map(&map, &objects);
+        assert!(report.unreachable.is_empty());
+        assert_eq!(report.blocked_spawns, vec![2]);
+    }
+
+    #[test]
+    fn generated_maps_validate() {
+        let monsters = load_monsters(MONSTERS_FILE).unwrap();
+        for &generator in &[MapGenerator::Rooms, MapGenerator::Bsp, MapGenerator::Caves] {
+            for seed in 1..5 {
+                let mut rng = GameRng::new(seed);
+                let player = Object::new(0, 0, '@', "player", WHITE, true);
+                let mut objects = vec![player];
+                let map = make_map(&mut objects, 1, generator, &monsters, &mut rng);
+                let report = validate_map(&map, &objects);
+                assert!(report.is_ok(), "{:?} seed {}: {:?}", generator, seed, report);
+            }
+        }
+    }
+
+    #[test]
+    fn dijkstra_adds_goal_weights() {
+        let map = small_map(9, 1, &[]);
+        let mut dijkstra = DijkstraMap::default();
+        dijkstra.update(&map, &[((0, 0), 0), ((8, 0), 4)], open);
+        let distances: Vec<_> = (0..9).map(|x| dijkstra.distance(x, 0).unwrap()).collect();
+        assert_eq!(distances, vec![0, 1, 2, 3, 4, 5, 6, 5, 4]);
+        assert_eq!(dijkstra.distance(9, 0), None);
+        assert_eq!(dijkstra.distance(-1, 0), None);
+    }
+
+    #[test]
+    fn dijkstra_walks_around_walls() {
+        // wall down x = 2 with a gap at the bottom
+        let map = small_map(5, 5, &[(2, 0), (2, 1), (2, 2), (2, 3)]);
+        let mut dijkstra = DijkstraMap::default();
+        dijkstra.update(&map, &[((0, 0), 0)], open);
+        assert_eq!(dijkstra.distance(4, 0), Some(8));
+        assert_eq!(dijkstra.distance(2, 0), None);
+        assert_eq!(dijkstra.best_step((4, 0), &map, &[]), Some((-1, 1)));
+    }
+
+    #[test]
+    fn dijkstra_unreachable_tiles() {
+        let map = small_map(5, 5, &[(2, 0), (2, 1), (2, 2), (2, 3), (2, 4)]);
+        let mut dijkstra = DijkstraMap::default();
+        dijkstra.update(&map, &[((0, 0), 0)], open);
+        assert_eq!(dijkstra.distance(1, 4), Some(4));
+        assert_eq!(dijkstra.distance(4, 0), None);
+        assert_eq!(dijkstra.best_step((4, 0), &map, &[]), None);
+    }
+
+    #[test]
+    fn dijkstra_stops_at_limit() {
+        let map = small_map(9, 1, &[]);
+        let mut dijkstra = DijkstraMap::default();
+        dijkstra.update_within(&map, &[((0, 0), 0)], 3, open);
+        assert_eq!(dijkstra.distance(3, 0), Some(3));
+        assert_eq!(dijkstra.distance(4, 0), None);
+        assert!(!dijkstra.update_within(&map, &[((0, 0), 0)], 3, open));
+        assert!(dijkstra.update(&map, &[((0, 0), 0)], open));
+        assert_eq!(dijkstra.distance(8, 0), Some(8));
+
+        // out of range, heads straight for the target instead
+        dijkstra.update_within(&map, &[((0, 0), 0)], 3, open);
+        let mut objects = vec![monster(6, 0)];
+        move_downhill(0, &dijkstra, (0, 0), &map, &mut objects);
+        assert_eq!(objects[0].pos(), (5, 0));
+    }
+
+    #[test]
+    fn dijkstra_steps_around_blocking_objects() {
+        let map = small_map(5, 1, &[]);
+        let mut dijkstra = DijkstraMap::default();
+        dijkstra.update(&map, &[((0, 0), 0)], open);
+        assert_eq!(dijkstra.best_step((3, 0), &map, &[]), Some((-1, 0)));
+
+        // something in the only way, wait behind it
+        let objects = [monster(2, 0)];
+        assert_eq!(dijkstra.distance(3, 0), Some(3));
+        assert_eq!(dijkstra.best_step((3, 0), &map, &objects), None);
+
+        // with room to pass, go around
+        let map = small_map(5, 2, &[]);
+        dijkstra.update(&map, &[((0, 0), 0)], open);
+        assert_eq!(dijkstra.best_step((3, 0), &map, &objects), Some((-1, 1)));
+    }
+
+    #[test]
+    fn dijkstra_flees_from_goals() {
+        let map = small_map(7, 1, &[]);
+        let mut chase = DijkstraMap::default();
+        chase.update(&map, &[((0, 0), 0)], open);
+        let mut flee = DijkstraMap::default();
+        flee.update_flee(&chase);
+        assert_eq!(flee.best_step((3, 0), &map, &[]), Some((1, 0)));
+
+        // cornered, nothing leads further away
+        assert_eq!(flee.best_step((6, 0), &map, &[]), None);
+        assert_eq!(flee.best_step((5, 0), &map, &[monster(6, 0)]), None);
+    }
+
+    #[test]
+    fn dijkstra_update_skips_unchanged() {
+        let mut map = small_map(5, 5, &[]);
+        let mut chase = DijkstraMap::default();
+        let mut flee = DijkstraMap::default();
+        assert!(chase.update(&map, &[((0, 0), 0)], open));
+        assert!(flee.update_flee(&chase));
+
+        assert!(!chase.update(&map, &[((0, 0), 0)], open));
+        assert!(!flee.update_flee(&chase));
+
+        assert!(chase.update(&map, &[((1, 0), 0)], open));
+        assert!(flee.update_flee(&chase));
+
+        map[3][3] = Tile::wall();
+        assert!(chase.update(&map, &[((1, 0), 0)], open));
+        assert_eq!(chase.distance(3, 3), None);
+        assert!(flee.update_flee(&chase));
+        assert!(!flee.update_flee(&chase));
+    }
+
+    // hp the player loses to a bezos of given speed standing next to
+    // them, over turns player actions costing cost each. effect is put
+    // on the player before they start
+    fn hits_taken(speed: i32, cost: i32, turns: usize, effect: Option<Effect>) -> i32 {
+        let (mut game, mut objects) = game_on(dug_map(&[Rect::new(1, 1, 10, 10)]), (3, 3));
+        let mut bezos = spawn("bezos", 4, 3);
+        bezos.speed = speed;
+        objects.push(bezos);
+        let mut backend = HeadlessBackend::new(vec![]);
+        let mut tcod = new_tcod(&mut backend, default_keymap());
+        look(&mut tcod, &game, &objects);
+
+        // one turn first so energy left from the start doesn't count
+        objects[PLAYER].energy -= cost;
+        run_until_player_turn(&tcod, &mut game, &mut objects);
+        let hp = objects[PLAYER].fighter.unwrap().hp;
+        if let Some(effect) = effect {
+            objects[PLAYER].add_effect(effect, &mut game.messages);
+        }
+        for _ in 0..turns {
+            objects[PLAYER].energy -= cost;
+            run_until_player_turn(&tcod, &mut game, &mut objects);
+        }
+        hp - objects[PLAYER].fighter.unwrap().hp
+    }
+
+    #[test]
+    fn scheduler_gives_turns_by_speed_and_cost() {
+        // bezos hits for 1 through the player's defense
+        assert_eq!(hits_taken(NORMAL_SPEED, MOVE_COST, 4, None), 4);
+        assert_eq!(hits_taken(2 * NORMAL_SPEED, MOVE_COST, 4, None), 8);
+        assert_eq!(hits_taken(NORMAL_SPEED / 2, MOVE_COST, 4, None), 2);
+        // item use takes half a turn
+        assert_eq!(hits_taken(NORMAL_SPEED, ITEM_COST, 4, None), 2);
+    }
+
+    fn effect(kind: EffectKind, turns: i32, strength: i32) -> Effect {
+        Effect { kind, turns, strength }
+    }
+
+    #[test]
+    fn effects_stack_or_refresh() {
+        let mut messages = Messages::new(MSG_HISTORY);
+        let mut bezos = spawn("bezos", 1, 1);
+
+        // poison piles up, keeps the strongest dose
+        bezos.add_effect(effect(EffectKind::Poison, 3, 1), &mut messages);
+        bezos.add_effect(effect(EffectKind::Poison, 2, 2), &mut messages);
+        // the rest only refresh to the longer one
+        bezos.add_effect(effect(EffectKind::Stun, 3, 1), &mut messages);
+        bezos.add_effect(effect(EffectKind::Stun, 2, 1), &mut messages);
+        bezos.add_effect(effect(EffectKind::Haste, 1, 1), &mut messages);
+        bezos.add_effect(effect(EffectKind::Haste, 4, 1), &mut messages);
+
+        assert_eq!(
+            bezos.effects,
+            vec![
+                effect(EffectKind::Poison, 5, 2),
+                effect(EffectKind::Stun, 3, 1),
+                effect(EffectKind::Haste, 4, 1),
+            ]
+        );
+        // announced once each
+        assert_eq!(messages.iter().count(), 3);
+        assert_eq!(bezos.current_speed(), 2 * NORMAL_SPEED);
+    }
+
+    #[test]
+    fn effects_tick_and_expire() {
+        let (mut game, mut objects) = game_on(dug_map(&[Rect::new(1, 1, 10, 10)]), (3, 3));
+        objects.push(spawn("bezos", 6, 6));
+        let Fighter { hp, xp: worth, .. } = objects[1].fighter.unwrap();
+        objects[1].add_effect(effect(EffectKind::Poison, 2, 1), &mut game.messages);
+        objects[1].add_effect(effect(EffectKind::Stun, 1, 1), &mut game.messages);
+
+        // stunned turn is skipped, poison still bites
+        assert!(!tick_effects(1, &mut game, &mut objects));
+        assert_eq!(objects[1].fighter.unwrap().hp, hp - 1);
+        assert!(!objects[1].has_effect(EffectKind::Stun));
+
+        assert!(tick_effects(1, &mut game, &mut objects));
+        assert_eq!(objects[1].fighter.unwrap().hp, hp - 2);
+        assert!(objects[1].effects.is_empty());
+        let last = game.messages.iter().last().unwrap();
+        assert_eq!(last.text, "bezos is no longer poisoned.");
+
+        // a poison kill pays out like any other
+        let xp = objects[PLAYER].fighter.unwrap().xp;
+        objects[1].add_effect(effect(EffectKind::Poison, 2, hp), &mut game.messages);
+        assert!(!tick_effects(1, &mut game, &mut objects));
+        assert!(!objects[1].alive);
+        assert_eq!(objects[PLAYER].fighter.unwrap().xp, xp + worth);
+    }
+
+    #[test]
+    fn stunned_player_loses_turns() {
+        // two lost turns on top of the one taken
+        let stun = effect(EffectKind::Stun, 2, 1);
+        assert_eq!(hits_taken(NORMAL_SPEED, MOVE_COST, 1, Some(stun)), 3);
+    }
+
+    #[test]
+    fn repeated_messages_collapse() {
+        let mut messages = Messages::new(MSG_HISTORY);
+        messages.add("You wait.", WHITE);
+        messages.add("You wait.", WHITE);
+        messages.add("You wait.", WHITE);
+        // same text in another color is a different message
+        messages.add("You wait.", RED);
+        messages.add("You wait.", WHITE);
+
+        let lines: Vec<_> = messages.iter().map(|message| message.line()).collect();
+        assert_eq!(lines, vec!["You wait. x3", "You wait.", "You wait."]);
+    }
+
+    #[test]
+    fn history_keeps_newest_messages() {
+        let mut messages = Messages::new(3);
+        for i in 0..5 {
+            messages.add(format!("message {}", i), WHITE);
+        }
+        // repeats don't take up room
+        messages.add("message 4", WHITE);
+        let texts: Vec<_> = messages.iter().map(|message| message.text.as_str()).collect();
+        assert_eq!(texts, vec!["message 2", "message 3", "message 4"]);
+
+        messages.set_capacity(1);
+        assert_eq!(messages.iter().map(|message| message.line()).collect::<Vec<_>>(), vec!["message 4 x2"]);
+    }
+
+    #[test]
+    fn wrap_text_breaks_between_words() {
+        assert_eq!(wrap_text("a bb ccc dd", 6), vec!["a bb", "ccc dd"]);
+        assert_eq!(wrap_text("", 6), vec![""]);
+        // a word wider than a line is cut into line sized pieces
+        assert_eq!(wrap_text("x abcdefghij y", 4), vec!["x", "abcd", "efgh", "ij y"]);
+        for line in wrap_text("a supercalifragilistic b", 5) {
+            assert!(line.chars().count() <= 5, "{:?} too long", line);
+        }
+    }
+
+    // key press as the backend would report it
+    fn key(name: &str) -> Key {
+        match scripted_event(name) {
+            Some(Event::Key(key)) => key,
+            _ => panic!("no key called {}", name),
+        }
+    }
+
+    #[test]
+    fn key_specs_parse() {
+        let spec = KeySpec::parse("alt+ctrl+pageup").unwrap();
+        assert_eq!((spec.code, spec.alt, spec.ctrl), (KeyCode::PageUp, true, true));
+        let spec = KeySpec::parse("kp7").unwrap();
+        assert_eq!((spec.code, spec.text.as_str()), (KeyCode::NumPad7, ""));
+        let spec = KeySpec::parse(">").unwrap();
+        assert_eq!((spec.code, spec.text.as_str()), (KeyCode::Text, ">"));
+        assert!(spec.matches(key(">")));
+        assert!(!spec.matches(key("<")));
+
+        assert_eq!(KeySpec::parse("f13"), None);
+        assert_eq!(KeySpec::parse("alt+"), None);
+        assert_eq!(KeySpec::parse("shift+a"), None);
+    }
+
+    #[test]
+    fn default_keymap_finds_commands() {
+        let keymap = default_keymap();
+        assert_eq!(keymap.command_for(key("up")), Some(Command::MoveUp));
+        assert_eq!(keymap.command_for(key("k")), Some(Command::MoveUp));
+        assert_eq!(keymap.command_for(key("y")), Some(Command::MoveUpLeft));
+        assert_eq!(keymap.command_for(key(".")), Some(Command::Wait));
+        assert_eq!(keymap.command_for(key("enter")), None);
+        let mut alt_enter = key("enter");
+        alt_enter.alt = true;
+        assert_eq!(keymap.command_for(alt_enter), Some(Command::Fullscreen));
+    }
+
+    #[test]
+    fn keys_file_overrides_and_errors() {
+        let mut keymap = default_keymap();
+        parse_keymap("move_up = [\"w\"]", &mut keymap).unwrap();
+        assert_eq!(keymap.command_for(key("w")), Some(Command::MoveUp));
+        assert_eq!(keymap.command_for(key("k")), None);
+        // other commands keep their defaults
+        assert_eq!(keymap.command_for(key("j")), Some(Command::MoveDown));
+
+        let error = |text: &str| parse_keymap(text, &mut default_keymap()).unwrap_err();
+        assert_eq!(error("jump = [\"space\"]"), "unknown command: jump");
+        assert_eq!(error("wait = [\"f13\"]"), "unknown key: f13");
+        assert_eq!(error("wait = [\"k\"]"), "k is bound to both move_up and wait");
+        assert!(error("wait = \"k\"").contains("invalid type"));
+    }
+
+    // player at 3,5 in a room, hurt so there's something to rest for
+    fn hurt_in_room() -> (Game, Vec<Object>) {
+        let (game, mut objects) = game_on(dug_map(&[Rect::new(1, 1, 10, 10)]), (3, 5));
+        objects[PLAYER].fighter.as_mut().unwrap().hp -= 10;
+        (game, objects)
+    }
+
+    #[test]
+    fn rest_heals_only_with_regeneration() {
+        let (mut game, mut objects) = hurt_in_room();
+        let mut backend = HeadlessBackend::new(vec![]);
+        let mut tcod = new_tcod(&mut backend, default_keymap());
+        look(&mut tcod, &game, &objects);
+
+        // haste counts the turns that went by
+        objects[PLAYER].add_effect(effect(EffectKind::Haste, 1000, 1), &mut game.messages);
+        rest(&tcod, &mut game, &mut objects);
+        assert_eq!(objects[PLAYER].fighter.unwrap().hp, 20);
+        assert_eq!(objects[PLAYER].effects[0].turns, 1000 - REST_MAX_TURNS);
+        assert_eq!(game.messages.iter().last().unwrap().text, "You get up, too restless to sleep.");
+
+        objects[PLAYER].add_effect(effect(EffectKind::Regeneration, 50, 1), &mut game.messages);
+        rest(&tcod, &mut game, &mut objects);
+        assert_eq!(objects[PLAYER].fighter.unwrap().hp, 30);
+        assert_eq!(game.messages.iter().last().unwrap().text, "You feel rested.");
+    }
+
+    #[test]
+    fn rest_stops_when_a_monster_shows_up() {
+        let (mut game, mut objects) = hurt_in_room();
+        // dead end just past torch light, the only way out is into view
+        create_h_tunnel(10, 14, 5, &mut game.map);
+        let mut bezos = spawn("bezos", 14, 5);
+        bezos.ai = Some(Ai::Confused { previous_ai: Box::new(Ai::Basic), num_turns: 10 * REST_MAX_TURNS });
+        objects.push(bezos);
+        let mut backend = HeadlessBackend::new(vec![]);
+        let mut tcod = new_tcod(&mut backend, default_keymap());
+        look(&mut tcod, &game, &objects);
+        assert_eq!(visible_monster(&tcod, &objects), None);
+
+        rest(&tcod, &mut game, &mut objects);
+        assert_eq!(objects[1].pos(), (13, 5));
+        assert_eq!(
+            game.messages.iter().last().unwrap().text,
+            "You stop resting, bezos comes into view."
+        );
+    }
+
+    // room around the player at 3,3 and a corridor east along y = 4 to
+    // x = 40, lit and explored as far as the player can see
+    fn room_and_corridor() -> (Game, Vec<Object>) {
+        let mut map = dug_map(&[Rect::new(1, 1, 6, 6)]);
+        create_h_tunnel(6, 40, 4, &mut map);
+        game_on(map, (3, 3))
+    }
+
+    // lays out fov and draws once, marking what's in view explored
+    fn first_frame(tcod: &mut Tcod, game: &mut Game, objects: &[Object]) {
+        initialise_fov(tcod, &game.map);
+        render_all(tcod, game, objects, true);
+    }
+
+    // first seeded level with no monster in view at the start
+    fn quiet_level(monsters: &[MonsterTemplate]) -> (u64, Game, Vec<Object>) {
+        (1..)
+            .map(|seed| {
+                let (game, objects) = new_game(seed, MapGenerator::Rooms, monsters, MSG_HISTORY);
+                (seed, game, objects)
+            })
+            .find(|(_, game, objects)| {
+                let mut backend = HeadlessBackend::new(vec![]);
+                let mut tcod = new_tcod(&mut backend, default_keymap());
+                look(&mut tcod, game, objects);
+                visible_monster(&tcod, objects).is_none()
+            })
+            .unwrap()
+    }
+
+    fn last_message(game: &Game) -> String {
+        game.messages.iter().last().map_or(String::new(), |message| message.text.clone())
+    }
+
+    #[test]
+    fn explore_stops_when_done() {
+        let (mut game, mut objects) = room_and_corridor();
+        let mut backend = HeadlessBackend::new(vec![]);
+        let mut tcod = new_tcod(&mut backend, default_keymap());
+        first_frame(&mut tcod, &mut game, &objects);
+
+        auto_explore(&mut tcod, &mut game, &mut objects);
+        assert_eq!(last_message(&game), "Nothing left to explore here.");
+        assert!(game.map.iter().flatten().all(|tile| tile.blocked || tile.explored));
+        assert!(objects[PLAYER].x > 20);
+    }
+
+    #[test]
+    fn explore_stops_for_monsters() {
+        let (mut game, mut objects) = room_and_corridor();
+        objects.push(spawn("bezos", 38, 4));
+        let mut backend = HeadlessBackend::new(vec![]);
+        let mut tcod = new_tcod(&mut backend, default_keymap());
+        first_frame(&mut tcod, &mut game, &objects);
+
+        auto_explore(&mut tcod, &mut game, &mut objects);
+        assert_eq!(last_message(&game), "You stop exploring, bezos comes into view.");
+        assert!(tcod.fov.is_in_fov(objects[1].x, objects[1].y));
+
+        // and won't start again while it's there
+        auto_explore(&mut tcod, &mut game, &mut objects);
+        assert_eq!(last_message(&game), "You can't explore with bezos around.");
+    }
+
+    #[test]
+    fn explore_stops_when_hurt_or_blocked() {
+        let (mut game, mut objects) = room_and_corridor();
+        let mut backend = HeadlessBackend::new(vec![]);
+        let mut tcod = new_tcod(&mut backend, default_keymap());
+        first_frame(&mut tcod, &mut game, &objects);
+
+        objects[PLAYER].add_effect(effect(EffectKind::Poison, 1, 1), &mut game.messages);
+        auto_explore(&mut tcod, &mut game, &mut objects);
+        assert_eq!(last_message(&game), "You stop exploring, something hurts.");
+        assert_ne!(objects[PLAYER].pos(), (3, 3));
+
+        // something with no fighter plugs the corridor
+        objects.push(monster(12, 4));
+        auto_explore(&mut tcod, &mut game, &mut objects);
+        assert_eq!(last_message(&game), "You stop exploring, something is in the way.");
+        assert_eq!(objects[PLAYER].pos(), (11, 4));
+    }
+
+    #[test]
+    fn explore_runs_from_a_script() {
+        let monsters = load_monsters(MONSTERS_FILE).unwrap();
+        let (seed, _, start) = quiet_level(&monsters);
+        let start = start[PLAYER].pos();
+
+        let events = vec![scripted_event("o").unwrap()];
+        let (_, game, objects) =
+            run_script(events, seed, MapGenerator::Rooms, &monsters, default_keymap(), MSG_HISTORY);
+
+        // the key after exploring isn't eaten as an interrupt
+        assert_ne!(objects[PLAYER].pos(), start);
+        let last = last_message(&game);
+        assert!(last.starts_with("You stop exploring") || last.starts_with("Nothing left"), "{}", last);
+    }
+
+    #[test]
+    fn travel_walks_known_tiles_to_the_stairs() {
+        let (mut game, mut objects) = room_and_corridor();
+        objects.push(Object::new(40, 4, '>', "stairs", WHITE, false));
+        let mut backend = HeadlessBackend::new(vec![]);
+        let mut tcod = new_tcod(&mut backend, default_keymap());
+        first_frame(&mut tcod, &mut game, &objects);
+
+        // end of the corridor hasn't been seen yet
+        travel(&mut tcod, &mut game, &mut objects, &[(40, 4)]);
+        assert_eq!(last_message(&game), "You don't know the way there.");
+        assert_eq!(objects[PLAYER].pos(), (3, 3));
+
+        for tile in game.map.iter_mut().flatten() {
+            tile.explored = true;
+        }
+        travel(&mut tcod, &mut game, &mut objects, &[(40, 4)]);
+        assert_eq!(objects[PLAYER].pos(), objects[1].pos());
+        assert!(objects[1].is_stairs());
+    }
+
+    #[test]
+    fn travel_to_stairs_on_a_generated_level() {
+        let monsters = load_monsters(MONSTERS_FILE).unwrap();
+        let (_, mut game, mut objects) = quiet_level(&monsters);
+        for tile in game.map.iter_mut().flatten() {
+            tile.explored = true;
+        }
+        let stairs: Vec<_> = objects.iter().filter(|object| object.is_stairs()).map(|object| object.pos()).collect();
+        assert_eq!(stairs.len(), 1);
+
+        let mut backend = HeadlessBackend::new(vec![]);
+        let mut tcod = new_tcod(&mut backend, default_keymap());
+        first_frame(&mut tcod, &mut game, &objects);
+        travel(&mut tcod, &mut game, &mut objects, &stairs);
+
+        // gets there unless a monster turns up on the way
+        let last = last_message(&game);
+        assert!(objects[PLAYER].pos() == stairs[0] || last.starts_with("You stop travelling"), "{}", last);
+    }
+}